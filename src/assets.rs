@@ -0,0 +1,427 @@
+use ggez::audio::SoundData;
+use ggez::graphics::{Color, Font, Image};
+use ggez::{Context, GameError, GameResult};
+
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::atlas::Atlas;
+use crate::audio::{MusicTracks, SoundConfig};
+use crate::ecs::{components, resources};
+
+pub const MANIFEST_PATH: &str = "/assets.ron";
+pub const BALANCE_PATH: &str = "/balance.ron";
+
+// assets that systems look up by key, these get placeholders when the manifest lacks them
+const REQUIRED_SPRITESHEETS: [&str; 2] = ["bullets", "enemies"];
+const REQUIRED_ANIMATIONS: [&str; 1] = ["explosion"];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AnimationEntry {
+    pub image: String,
+    pub frames: u8,
+    pub sheet_width: u8,
+    #[serde(default)]
+    pub looping: bool,
+}
+
+// every asset the game uses, by key. paths are relative to the resources directory
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct AssetManifest {
+    pub sprites: BTreeMap<String, String>,
+    // paths to atlas sidecars, the atlas points to its image
+    pub spritesheets: BTreeMap<String, String>,
+    pub animations: BTreeMap<String, AnimationEntry>,
+    pub sounds: BTreeMap<String, String>,
+    pub music: BTreeMap<String, String>,
+    pub fonts: BTreeMap<String, String>,
+    // volume, pitch variation and instance caps for sounds and music, keyed the same way
+    #[serde(default)]
+    pub sound_config: BTreeMap<String, SoundConfig>,
+    #[serde(default)]
+    pub music_tracks: MusicTracks,
+    // the ships that can be picked, see resources::Ship
+    #[serde(default)]
+    pub ships: Vec<resources::Ship>,
+}
+
+impl AssetManifest {
+    pub fn load(ctx: &mut Context) -> GameResult<Self> {
+        let file = ggez::filesystem::open(ctx, MANIFEST_PATH)?;
+        ron::de::from_reader(file).map_err(|e| {
+            GameError::ResourceLoadError(format!("error parsing asset manifest: {}", e))
+        })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .map_err(|e| format!("error opening asset manifest {}: {}", path.display(), e))?;
+        ron::de::from_reader(file)
+            .map_err(|e| format!("error parsing asset manifest {}: {}", path.display(), e))
+    }
+}
+
+pub fn load_balance(ctx: &mut Context) -> GameResult<resources::Balance> {
+    let file = ggez::filesystem::open(ctx, BALANCE_PATH)?;
+    parse_balance(file).map_err(GameError::ResourceLoadError)
+}
+
+pub fn balance_from_file(resources_dir: &Path) -> Result<resources::Balance, String> {
+    let path = resolve(resources_dir, BALANCE_PATH);
+    let file = std::fs::File::open(&path)
+        .map_err(|e| format!("error opening balance {}: {}", path.display(), e))?;
+    parse_balance(file)
+}
+
+fn parse_balance<R: std::io::Read>(reader: R) -> Result<resources::Balance, String> {
+    let balance: resources::Balance =
+        ron::de::from_reader(reader).map_err(|e| format!("error parsing balance: {}", e))?;
+    let missing = balance.missing();
+    if missing.is_empty() {
+        Ok(balance)
+    } else {
+        Err(format!("error in balance: {}", missing.join(", ")))
+    }
+}
+
+// bright magenta so that missing sprites are obvious
+fn placeholder_image(ctx: &mut Context) -> Image {
+    Image::solid(ctx, 16, Color::new(1.0, 0.0, 1.0, 1.0)).expect("error creating placeholder image")
+}
+
+// loads everything in the manifest into the world. missing or broken assets are replaced
+// with placeholders and all of the errors are returned together instead of crashing.
+pub fn load(ctx: &mut Context, manifest: &AssetManifest, world: &mut World) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    let mut sprites = HashMap::new();
+    manifest.sprites.iter().for_each(|(key, path)| {
+        let image = Image::new(ctx, path).unwrap_or_else(|e| {
+            errors.push(format!("sprite {} ({}): {}", key, path, e));
+            placeholder_image(ctx)
+        });
+        sprites.insert(key.clone(), image);
+    });
+
+    let mut spritesheets = HashMap::new();
+    manifest.spritesheets.iter().for_each(|(key, atlas_path)| {
+        let atlas = Atlas::load(ctx, atlas_path).unwrap_or_else(|e| {
+            errors.push(format!("spritesheet {} ({}): {}", key, atlas_path, e));
+            Atlas::default()
+        });
+        let image = Image::new(ctx, &atlas.image).unwrap_or_else(|e| {
+            errors.push(format!("spritesheet {} ({}): {}", key, atlas.image, e));
+            placeholder_image(ctx)
+        });
+        spritesheets.insert(
+            key.clone(),
+            Arc::new(Mutex::new(resources::SpriteSheet::new(image, atlas))),
+        );
+    });
+    REQUIRED_SPRITESHEETS.iter().for_each(|key| {
+        if !spritesheets.contains_key(*key) {
+            errors.push(format!("spritesheet {} is missing from the manifest", key));
+            let spritesheet = resources::SpriteSheet::new(placeholder_image(ctx), Atlas::default());
+            spritesheets.insert(key.to_string(), Arc::new(Mutex::new(spritesheet)));
+        }
+    });
+
+    let mut animated_sprites = HashMap::new();
+    manifest.animations.iter().for_each(|(key, animation)| {
+        let (image, frames, sheet_width) = match Image::new(ctx, &animation.image) {
+            Ok(image) => (image, animation.frames, animation.sheet_width),
+            Err(e) => {
+                errors.push(format!("animation {} ({}): {}", key, animation.image, e));
+                (placeholder_image(ctx), 1, 1)
+            }
+        };
+        animated_sprites.insert(
+            key.clone(),
+            components::AnimatedSprite::new(
+                key,
+                Some(image),
+                frames,
+                sheet_width,
+                !animation.looping,
+            ),
+        );
+    });
+    REQUIRED_ANIMATIONS.iter().for_each(|key| {
+        if !animated_sprites.contains_key(*key) {
+            errors.push(format!("animation {} is missing from the manifest", key));
+            let image = Some(placeholder_image(ctx));
+            let animated_sprite = components::AnimatedSprite::new(key, image, 1, 1, true);
+            animated_sprites.insert(key.to_string(), animated_sprite);
+        }
+    });
+
+    // sounds that fail to load are replaced by empty data, which fails to play with a warning
+    let mut load_sounds = |kind: &str, entries: &BTreeMap<String, String>| {
+        entries
+            .iter()
+            .map(|(key, path)| {
+                let sound = SoundData::new(ctx, path).unwrap_or_else(|e| {
+                    errors.push(format!("{} {} ({}): {}", kind, key, path, e));
+                    SoundData::from_bytes(&[])
+                });
+                (key.clone(), sound)
+            })
+            .collect::<HashMap<_, _>>()
+    };
+    let sounds = load_sounds("sound", &manifest.sounds);
+    let music = load_sounds("music", &manifest.music);
+
+    let font = match manifest.fonts.get("main") {
+        Some(path) => Font::new(ctx, path).unwrap_or_else(|e| {
+            errors.push(format!("font main ({}): {}", path, e));
+            Font::default()
+        }),
+        None => {
+            errors.push("font main is missing from the manifest".to_string());
+            Font::default()
+        }
+    };
+
+    world.insert(resources::Sprites(sprites));
+    world.insert(resources::SpriteSheets(spritesheets));
+    world.insert(resources::AnimatedSprites(animated_sprites));
+    world.insert(resources::Sounds(sounds));
+    world.insert(resources::Music(music));
+    world.insert(sound_configs(manifest));
+    world.insert(manifest.music_tracks.clone());
+    world.insert(resources::Ships(manifest.ships.clone()));
+    world.insert(resources::GameFont(font));
+    match load_balance(ctx) {
+        Ok(balance) => world.insert(balance),
+        Err(e) => errors.push(format!("balance ({}): {}", BALANCE_PATH, e)),
+    }
+
+    errors
+}
+
+pub fn sound_configs(manifest: &AssetManifest) -> resources::SoundConfigs {
+    resources::SoundConfigs(
+        manifest
+            .sound_config
+            .iter()
+            .map(|(key, config)| (key.clone(), *config))
+            .collect(),
+    )
+}
+
+// manifest paths start with a slash, like paths in ggez's filesystem
+pub fn resolve(resources_dir: &Path, path: &str) -> PathBuf {
+    resources_dir.join(path.trim_start_matches('/'))
+}
+
+// loads what the game logic needs without a ggez context, for running headless.
+// spritesheets only get their atlas and nothing that can be drawn is loaded
+pub fn load_headless(resources_dir: &Path, world: &mut World) -> Vec<String> {
+    let manifest = match AssetManifest::from_file(resolve(resources_dir, MANIFEST_PATH)) {
+        Ok(manifest) => manifest,
+        Err(e) => return vec![e],
+    };
+
+    let mut errors = Vec::new();
+    let spritesheets = manifest
+        .spritesheets
+        .iter()
+        .filter_map(|(key, atlas_path)| {
+            let atlas = Atlas::from_file(resolve(resources_dir, atlas_path))
+                .map_err(|e| errors.push(format!("spritesheet {}: {}", key, e)))
+                .ok()?;
+            let (width, height) = image::image_dimensions(resolve(resources_dir, &atlas.image))
+                .map_err(|e| {
+                    errors.push(format!("spritesheet {} ({}): {}", key, atlas.image, e));
+                })
+                .ok()?;
+            let spritesheet =
+                resources::SpriteSheet::headless(atlas, (width as u16, height as u16));
+            Some((key.clone(), Arc::new(Mutex::new(spritesheet))))
+        })
+        .collect();
+
+    let animated_sprites = manifest
+        .animations
+        .iter()
+        .map(|(key, animation)| {
+            let animated_sprite = components::AnimatedSprite::new(
+                key,
+                None,
+                animation.frames,
+                animation.sheet_width,
+                !animation.looping,
+            );
+            (key.clone(), animated_sprite)
+        })
+        .collect();
+
+    world.insert(resources::Sprites::default());
+    world.insert(resources::SpriteSheets(spritesheets));
+    world.insert(resources::AnimatedSprites(animated_sprites));
+    world.insert(resources::Sounds::default());
+    world.insert(resources::Music::default());
+    world.insert(sound_configs(&manifest));
+    world.insert(manifest.music_tracks.clone());
+    world.insert(resources::Ships(manifest.ships.clone()));
+    match balance_from_file(resources_dir) {
+        Ok(balance) => world.insert(balance),
+        Err(e) => errors.push(e),
+    }
+
+    errors
+}
+
+// checks that everything in the manifest exists and is well formed, without a ggez context
+pub fn validate(resources_dir: &Path) -> Vec<String> {
+    let manifest = match AssetManifest::from_file(resolve(resources_dir, MANIFEST_PATH)) {
+        Ok(manifest) => manifest,
+        Err(e) => return vec![e],
+    };
+
+    let mut errors = Vec::new();
+    let resolve = |path: &str| resolve(resources_dir, path);
+    let mut check_image = |kind: &str, key: &str, path: &str| -> Option<(u32, u32)> {
+        match image::image_dimensions(resolve(path)) {
+            Ok(dimensions) => Some(dimensions),
+            Err(e) => {
+                errors.push(format!("{} {} ({}): {}", kind, key, path, e));
+                None
+            }
+        }
+    };
+
+    manifest.sprites.iter().for_each(|(key, path)| {
+        check_image("sprite", key, path);
+    });
+
+    manifest.animations.iter().for_each(|(key, animation)| {
+        check_image("animation", key, &animation.image);
+    });
+
+    let mut atlas_errors = Vec::new();
+    manifest.spritesheets.iter().for_each(|(key, atlas_path)| {
+        match Atlas::from_file(resolve(atlas_path)) {
+            Ok(atlas) => {
+                if let Some((width, height)) = check_image("spritesheet", key, &atlas.image) {
+                    atlas.frames.iter().for_each(|frame| {
+                        let rect = frame.rect;
+                        if rect.x + rect.w > width || rect.y + rect.h > height {
+                            atlas_errors.push(format!(
+                                "spritesheet {}: frame {} is outside of the {}x{} image",
+                                key, frame.name, width, height
+                            ));
+                        }
+                    });
+                }
+            }
+            Err(e) => atlas_errors.push(format!("spritesheet {}: {}", key, e)),
+        }
+    });
+
+    manifest
+        .animations
+        .iter()
+        .filter(|(_, animation)| animation.frames > animation.sheet_width)
+        .for_each(|(key, animation)| {
+            errors.push(format!(
+                "animation {}: {} frames don't fit in a sheet {} frames wide",
+                key, animation.frames, animation.sheet_width
+            ));
+        });
+
+    [
+        ("sound", &manifest.sounds),
+        ("music", &manifest.music),
+        ("font", &manifest.fonts),
+    ]
+    .iter()
+    .for_each(|(kind, entries)| {
+        entries
+            .iter()
+            .filter(|(_, path)| !resolve(path).is_file())
+            .for_each(|(key, path)| {
+                errors.push(format!("{} {} ({}): file not found", kind, key, path));
+            });
+    });
+
+    manifest
+        .sound_config
+        .keys()
+        .filter(|key| !manifest.sounds.contains_key(*key) && !manifest.music.contains_key(*key))
+        .for_each(|key| {
+            errors.push(format!(
+                "sound config {} doesn't match any sound or music",
+                key
+            ));
+        });
+
+    let tracks = &manifest.music_tracks;
+    tracks
+        .waves
+        .iter()
+        .map(|(_, track)| track)
+        .chain(tracks.boss.iter())
+        .chain(tracks.game_over.iter())
+        .filter(|track| !manifest.music.contains_key(*track))
+        .for_each(|track| {
+            errors.push(format!("music track {} is missing from music", track));
+        });
+
+    manifest.ships.iter().enumerate().for_each(|(i, ship)| {
+        if manifest.ships[..i]
+            .iter()
+            .any(|other| other.name == ship.name)
+        {
+            errors.push(format!("ship {} is listed more than once", ship.name));
+        }
+        let deflector = &ship.deflector;
+        [
+            (ship.speed > 0.0, "speed has to be above 0"),
+            (
+                ship.hitbox.2 > 0.0 && ship.hitbox.3 > 0.0,
+                "hitbox has to have a size",
+            ),
+            (
+                deflector.active_frames > 0,
+                "active_frames has to be at least 1",
+            ),
+            (
+                deflector.level_frames > 0,
+                "level_frames has to be at least 1",
+            ),
+            (deflector.max_level > 0, "max_level has to be at least 1"),
+        ]
+        .iter()
+        .filter(|(valid, _)| !valid)
+        .for_each(|(_, problem)| {
+            errors.push(format!("ship {}: {}", ship.name, problem));
+        });
+        let sprites = &ship.sprites;
+        [&sprites.normal, &sprites.deflector, &sprites.cooldown]
+            .iter()
+            .filter(|key| !manifest.sprites.contains_key(**key))
+            .for_each(|key| {
+                errors.push(format!(
+                    "ship {}: sprite {} is missing from sprites",
+                    ship.name, key
+                ));
+            });
+    });
+
+    if !manifest.fonts.contains_key("main") {
+        errors.push("font main is missing from the manifest".to_string());
+    }
+
+    if let Err(e) = balance_from_file(resources_dir) {
+        errors.push(e);
+    }
+
+    errors.append(&mut atlas_errors);
+    errors
+}
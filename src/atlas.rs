@@ -0,0 +1,182 @@
+use ggez::graphics::Rect;
+use ggez::{Context, GameError, GameResult};
+
+use serde::{Deserialize, Serialize};
+
+use std::ffi::OsStr;
+use std::path::Path;
+
+// rects in atlases are in pixels of the source image
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PixelRect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AtlasFrame {
+    pub name: String,
+    pub rect: PixelRect,
+    // the point of the frame that gets placed at the entity's position, in pixels
+    #[serde(default)]
+    pub pivot: (f32, f32),
+    // suggested hitbox relative to the frame, in pixels: (x, y, w, h)
+    #[serde(default)]
+    pub hitbox: Option<(f32, f32, f32, f32)>,
+}
+
+// atlases are stored in RON sidecar files next to the image they describe
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct Atlas {
+    pub image: String,
+    pub frames: Vec<AtlasFrame>,
+}
+
+impl Atlas {
+    // loads an atlas through the ggez filesystem, paths start with a `/`
+    pub fn load(ctx: &mut Context, path: &str) -> GameResult<Self> {
+        let file = ggez::filesystem::open(ctx, path)?;
+        ron::de::from_reader(file).map_err(|e| {
+            GameError::ResourceLoadError(format!("error parsing atlas {}: {}", path, e))
+        })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .map_err(|e| format!("error opening atlas {}: {}", path.display(), e))?;
+        ron::de::from_reader(file)
+            .map_err(|e| format!("error parsing atlas {}: {}", path.display(), e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let pretty = ron::ser::PrettyConfig::default();
+        let serialized = ron::ser::to_string_pretty(self, pretty)
+            .map_err(|e| format!("error serializing atlas: {}", e))?;
+        std::fs::write(path.as_ref(), serialized)
+            .map_err(|e| format!("error writing atlas {}: {}", path.as_ref().display(), e))
+    }
+
+    pub fn frame_index(&self, name: &str) -> Option<usize> {
+        self.frames.iter().position(|frame| frame.name == name)
+    }
+
+    // the src rect of a frame, normalized to the size of the image
+    pub fn src_rect(&self, index: usize, image_size: (u16, u16)) -> Rect {
+        let (width, height) = (image_size.0 as f32, image_size.1 as f32);
+        match self.frames.get(index) {
+            Some(frame) => Rect::new(
+                frame.rect.x as f32 / width,
+                frame.rect.y as f32 / height,
+                frame.rect.w as f32 / width,
+                frame.rect.h as f32 / height,
+            ),
+            None => Rect::new(0.0, 0.0, 1.0, 1.0),
+        }
+    }
+}
+
+// packs every png in `input_dir` into a single sheet, writing `<output>.png` and `<output>.ron`.
+// images which already have an atlas sidecar are split into their named frames.
+pub fn pack(input_dir: &Path, output: &Path) -> Result<Atlas, String> {
+    use image::{imageops, RgbaImage};
+
+    let output_png = output.with_extension("png");
+
+    let mut entries: Vec<_> = std::fs::read_dir(input_dir)
+        .map_err(|e| format!("error reading {}: {}", input_dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension() == Some(OsStr::new("png")) && *path != output_png)
+        .collect();
+    entries.sort();
+
+    // frames cut out of existing atlases keep their pivot and hitbox
+    let mut images: Vec<(AtlasFrame, RgbaImage)> = Vec::new();
+    for path in entries.iter() {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut img = image::open(path)
+            .map_err(|e| format!("error loading {}: {}", path.display(), e))?
+            .to_rgba();
+
+        let sidecar = path.with_extension("ron");
+        if sidecar.exists() {
+            let atlas = Atlas::from_file(&sidecar)?;
+            atlas.frames.into_iter().for_each(|frame| {
+                let r = frame.rect;
+                let frame_img = imageops::crop(&mut img, r.x, r.y, r.w, r.h).to_image();
+                let name = format!("{}/{}", stem, frame.name);
+                images.push((AtlasFrame { name, ..frame }, frame_img));
+            });
+        } else {
+            let frame = AtlasFrame {
+                name: stem,
+                rect: PixelRect {
+                    x: 0,
+                    y: 0,
+                    w: img.width(),
+                    h: img.height(),
+                },
+                pivot: (0.0, 0.0),
+                hitbox: None,
+            };
+            images.push((frame, img));
+        }
+    }
+
+    // simple shelf packing, tallest images first, with a pixel of padding
+    // to avoid bleeding between frames
+    images.sort_by_key(|(_, img)| std::cmp::Reverse(img.height()));
+    let total_area: u32 = images
+        .iter()
+        .map(|(_, img)| (img.width() + 1) * (img.height() + 1))
+        .sum();
+    let widest = images
+        .iter()
+        .map(|(_, img)| img.width() + 1)
+        .max()
+        .unwrap_or(1);
+    let sheet_width = ((total_area as f32).sqrt() as u32)
+        .max(widest)
+        .next_power_of_two();
+
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+    images.iter_mut().for_each(|(frame, img)| {
+        if x + img.width() > sheet_width {
+            x = 0;
+            y += shelf_height + 1;
+            shelf_height = 0;
+        }
+        frame.rect.x = x;
+        frame.rect.y = y;
+        x += img.width() + 1;
+        shelf_height = shelf_height.max(img.height());
+    });
+    let sheet_height = (y + shelf_height).max(1);
+
+    let mut sheet = RgbaImage::new(sheet_width, sheet_height);
+    images.iter().for_each(|(frame, img)| {
+        imageops::replace(&mut sheet, img, frame.rect.x, frame.rect.y);
+    });
+    sheet
+        .save(&output_png)
+        .map_err(|e| format!("error writing {}: {}", output_png.display(), e))?;
+
+    let atlas = Atlas {
+        image: format!(
+            "/{}",
+            output_png
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default()
+        ),
+        frames: images.into_iter().map(|(frame, _)| frame).collect(),
+    };
+    atlas.save(output.with_extension("ron"))?;
+
+    Ok(atlas)
+}
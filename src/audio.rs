@@ -0,0 +1,343 @@
+use ggez::audio::{SoundData, SoundSource, Source, SpatialSource};
+use ggez::Context;
+
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+
+use crate::ecs::resources::{QueuedSound, SoundConfigs, Sounds};
+use crate::settings::Settings;
+
+// every volume is scaled by this so that 100% in the settings is a sane default
+const BASE_VOLUME: f32 = 0.04;
+
+// how loud the music is while the pause menu is open
+const DUCK_VOLUME: f32 = 0.35;
+
+// how long it takes for one music track to fade into the next
+const CROSSFADE_FRAMES: f32 = 90.0;
+
+// sounds past this many are dropped, lowest priority first
+const MAX_VOICES: usize = 24;
+
+// how far left or right a sound at the edge of the screen is placed. with the ears at -1 and 1,
+// a sound in the middle plays at 3/4 volume in both ears, which is made up for with PAN_GAIN
+const PAN_WIDTH: f32 = 0.6;
+const PAN_GAIN: f32 = 4.0 / 3.0;
+
+// per sound mixing options, set in the asset manifest
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SoundConfig {
+    pub volume: f32,
+    // pitch is randomly picked from 1.0 +- pitch_variation every time the sound plays
+    pub pitch_variation: f32,
+    // more copies of the sound than this are dropped instead of played
+    pub max_instances: usize,
+}
+
+impl Default for SoundConfig {
+    fn default() -> Self {
+        SoundConfig {
+            volume: 1.0,
+            pitch_variation: 0.0,
+            max_instances: 4,
+        }
+    }
+}
+
+// which music plays when, set in the asset manifest.
+// the music director system picks a track from this every frame
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MusicTracks {
+    // (first wave, track), the track of the last milestone that's been reached plays
+    pub waves: Vec<(u8, String)>,
+    // boss waves override the normal wave tracks
+    pub boss_waves: Vec<u8>,
+    pub boss: Option<String>,
+    // no track means the music fades out
+    pub game_over: Option<String>,
+}
+
+impl MusicTracks {
+    pub fn track(&self, wave: u8, dead: bool) -> Option<&str> {
+        if dead {
+            return self.game_over.as_deref();
+        }
+
+        if self.boss_waves.contains(&wave) && self.boss.is_some() {
+            return self.boss.as_deref();
+        }
+
+        self.waves
+            .iter()
+            .filter(|(start_wave, _)| *start_wave <= wave)
+            .max_by_key(|(start_wave, _)| *start_wave)
+            .map(|(_, track)| track.as_str())
+    }
+}
+
+// a playing music track, fading in or out
+struct MusicTrack {
+    key: String,
+    source: Option<Source>,
+    config: SoundConfig,
+    fade: f32,
+}
+
+// owns every playing sound so that they can be capped, and the music so it can be controlled.
+// sources can't be shared between threads, so this lives in the game state instead of the world
+pub struct Mixer {
+    master_volume: f32,
+    music_volume: f32,
+    sfx_volume: f32,
+    playing: HashMap<String, Vec<(SpatialSource, f32)>>,
+    music: Option<MusicTrack>,
+    fading_out: Vec<MusicTrack>,
+    ducked: bool,
+}
+
+impl Mixer {
+    pub fn new(settings: &Settings) -> Self {
+        Mixer {
+            master_volume: settings.master_volume,
+            music_volume: settings.music_volume,
+            sfx_volume: settings.sfx_volume,
+            playing: HashMap::new(),
+            music: None,
+            fading_out: Vec::new(),
+            ducked: false,
+        }
+    }
+
+    pub fn set_volumes(&mut self, settings: &Settings) {
+        self.master_volume = settings.master_volume;
+        self.music_volume = settings.music_volume;
+        self.sfx_volume = settings.sfx_volume;
+
+        let sfx_volume = self.sfx_gain();
+        self.playing
+            .values_mut()
+            .flatten()
+            .for_each(|(source, volume)| {
+                source.set_volume(*volume * sfx_volume);
+            });
+        self.update_music_volume();
+    }
+
+    fn sfx_gain(&self) -> f32 {
+        BASE_VOLUME * self.master_volume * self.sfx_volume
+    }
+
+    fn music_gain(&self) -> f32 {
+        let duck = if self.ducked { DUCK_VOLUME } else { 1.0 };
+        BASE_VOLUME * self.master_volume * self.music_volume * duck
+    }
+
+    fn update_music_volume(&mut self) {
+        let gain = self.music_gain();
+        self.music
+            .iter_mut()
+            .chain(self.fading_out.iter_mut())
+            .for_each(|track| {
+                if let Some(source) = &mut track.source {
+                    source.set_volume(gain * track.config.volume * track.fade);
+                }
+            });
+    }
+
+    // forgets about sounds that have finished and steps music fades, should be called every frame
+    pub fn update(&mut self) {
+        self.playing.values_mut().for_each(|sources| {
+            sources.retain(|(source, _)| source.playing());
+        });
+
+        let step = 1.0 / CROSSFADE_FRAMES;
+        if let Some(track) = &mut self.music {
+            track.fade = (track.fade + step).min(1.0);
+        }
+        self.fading_out.iter_mut().for_each(|track| {
+            track.fade -= step;
+            if track.fade <= 0.0 {
+                if let Some(source) = &mut track.source {
+                    source.stop();
+                }
+            }
+        });
+        self.fading_out.retain(|track| track.fade > 0.0);
+
+        self.update_music_volume();
+    }
+
+    // plays everything queued this frame. copies of a sound queued together are played once,
+    // louder and from their average position
+    pub fn play_queued(
+        &mut self,
+        ctx: &mut Context,
+        queued: &[QueuedSound],
+        sounds: &Sounds,
+        sound_configs: &SoundConfigs,
+    ) {
+        let mut collapsed = collapse_sounds(queued);
+        collapsed.sort_by_key(|(sound, _)| std::cmp::Reverse(sound.priority));
+
+        collapsed.iter().for_each(|(sound, loudness)| {
+            let voices: usize = self.playing.values().map(Vec::len).sum();
+            if voices >= MAX_VOICES {
+                return;
+            }
+
+            match sounds.0.get(sound.key) {
+                Some(data) => {
+                    let config = sound_configs.get(sound.key);
+                    let pan = sound.pos.map(|pos| pan(pos.x)).unwrap_or(0.0);
+                    self.play_sound(ctx, sound.key, data, config, pan, *loudness);
+                }
+                None => log::warn!("error getting sound {}", sound.key),
+            }
+        });
+    }
+
+    fn play_sound(
+        &mut self,
+        ctx: &mut Context,
+        key: &str,
+        data: &SoundData,
+        config: SoundConfig,
+        pan: f32,
+        loudness: f32,
+    ) {
+        let sfx_gain = self.sfx_gain();
+        let instances = self.playing.entry(key.to_string()).or_default();
+        if instances.len() >= config.max_instances {
+            return;
+        }
+
+        match SpatialSource::from_data(ctx, data.clone()) {
+            Ok(mut source) => {
+                let volume = config.volume * loudness * PAN_GAIN;
+                source.set_volume(volume * sfx_gain);
+                source.set_position([pan * PAN_WIDTH, 0.0, 0.0]);
+                if config.pitch_variation > 0.0 {
+                    let variation = config.pitch_variation;
+                    let pitch = 1.0 + thread_rng().gen_range(-variation, variation);
+                    source.set_pitch(pitch);
+                }
+                if source.play().is_err() {
+                    log::warn!("error playing sound {}", key);
+                } else {
+                    instances.push((source, volume));
+                }
+            }
+            Err(e) => log::warn!("error initializing sound source {}: {}", key, e),
+        }
+    }
+
+    // cross-fades to a looping music track, does nothing if it's already playing.
+    // a track that fails to load still counts as playing so that it isn't retried every frame
+    pub fn play_music(
+        &mut self,
+        ctx: &mut Context,
+        key: &str,
+        data: &SoundData,
+        config: SoundConfig,
+    ) {
+        if self.current_music() == Some(key) {
+            return;
+        }
+
+        self.stop_music();
+        let source = match Source::from_data(ctx, data.clone()) {
+            Ok(mut source) => {
+                source.set_repeat(true);
+                source.set_volume(0.0);
+                match source.play() {
+                    Ok(()) => Some(source),
+                    Err(e) => {
+                        log::warn!("error playing music {}: {}", key, e);
+                        None
+                    }
+                }
+            }
+            Err(e) => {
+                log::warn!("error initializing music source {}: {}", key, e);
+                None
+            }
+        };
+
+        self.music = Some(MusicTrack {
+            key: key.to_string(),
+            source,
+            config,
+            fade: 0.0,
+        });
+    }
+
+    pub fn current_music(&self) -> Option<&str> {
+        self.music.as_ref().map(|track| track.key.as_str())
+    }
+
+    // fades out the current track
+    pub fn stop_music(&mut self) {
+        if let Some(track) = self.music.take() {
+            self.fading_out.push(track);
+        }
+    }
+
+    pub fn pause_music(&mut self) {
+        self.music
+            .iter()
+            .chain(self.fading_out.iter())
+            .filter_map(|track| track.source.as_ref())
+            .for_each(|source| source.pause());
+    }
+
+    pub fn resume_music(&mut self) {
+        self.music
+            .iter()
+            .chain(self.fading_out.iter())
+            .filter_map(|track| track.source.as_ref())
+            .for_each(|source| source.resume());
+    }
+
+    // turns the music down, used while the game is paused
+    pub fn set_ducked(&mut self, ducked: bool) {
+        self.ducked = ducked;
+        self.update_music_volume();
+    }
+}
+
+// -1 is the left edge of the screen and 1 is the right edge
+fn pan(x: f32) -> f32 {
+    (x / crate::SCREEN_WIDTH * 2.0 - 1.0).clamp(-1.0, 1.0)
+}
+
+// merges sounds with the same key into one, with the highest priority and the average position.
+// each doubling of the number of copies makes the sound half again as loud, up to twice as loud
+fn collapse_sounds(queued: &[QueuedSound]) -> Vec<(QueuedSound, f32)> {
+    let mut collapsed: Vec<(QueuedSound, usize)> = Vec::new();
+
+    queued.iter().for_each(|sound| {
+        match collapsed
+            .iter_mut()
+            .find(|(other, _)| other.key == sound.key)
+        {
+            Some((other, count)) => {
+                other.priority = other.priority.max(sound.priority);
+                other.pos = match (other.pos, sound.pos) {
+                    (Some(a), Some(b)) => Some(a + (b - a) / (*count + 1) as f32),
+                    (a, b) => a.or(b),
+                };
+                *count += 1;
+            }
+            None => collapsed.push((*sound, 1)),
+        }
+    });
+
+    collapsed
+        .into_iter()
+        .map(|(sound, count)| (sound, (1.0 + 0.5 * (count as f32).log2()).min(2.0)))
+        .collect()
+}
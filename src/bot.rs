@@ -0,0 +1,358 @@
+use ggez::graphics::Rect;
+use specs::prelude::*;
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::ecs::components::*;
+use crate::ecs::resources::*;
+use crate::sim::{self, Input};
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// how many frames ahead the bot looks for bullets
+const LOOKAHEAD: u32 = 30;
+// the bot deflects bullets that will hit within this many frames
+const DEFLECT_FRAMES: u32 = 3;
+// extra room around the player's hitbox when dodging
+const MARGIN: f32 = 6.0;
+// where the bot goes back to when nothing is in the way
+const HOME_Y: f32 = SCREEN_HEIGHT * 0.8;
+// a long run, ten minutes at 60 fps
+pub const DEFAULT_MAX_FRAMES: u32 = 36_000;
+
+// up, down, left, right
+const MOVES: [(bool, bool, bool, bool); 9] = [
+    (false, false, false, false),
+    (true, false, false, false),
+    (false, true, false, false),
+    (false, false, true, false),
+    (false, false, false, true),
+    (true, false, true, false),
+    (true, false, false, true),
+    (false, true, true, false),
+    (false, true, false, true),
+];
+
+// a rect moving in a straight line, bullets are assumed to keep going the way they are
+struct Threat {
+    rect: Rect,
+    vel: Vector,
+}
+
+impl Threat {
+    fn at(&self, frame: u32) -> Rect {
+        let offset = self.vel * frame as f32;
+        Rect::new(
+            self.rect.x + offset.x,
+            self.rect.y + offset.y,
+            self.rect.w,
+            self.rect.h,
+        )
+    }
+}
+
+// plays the game through sim::Input like a person would. every frame it tries each direction,
+// predicts where it and every bullet will be, and takes the move that gets hit the least,
+// preferring to line up under an enemy. it always shoots, and taps deflect for bullets that are
+// about to hit when the deflector is ready
+pub fn input(world: &World) -> Input {
+    if world.fetch::<Dead>().0 {
+        return Input::default();
+    }
+
+    let player_entity = world.fetch::<PlayerEntity>().0;
+    let positions = world.read_storage::<Position>();
+    let vels = world.read_storage::<Velocity>();
+    let hitboxes = world.read_storage::<Hitbox>();
+    let (player_pos, player_vel, player_hitbox, player) = match (
+        positions.get(player_entity),
+        vels.get(player_entity),
+        hitboxes.get(player_entity),
+        world.read_storage::<Player>().get(player_entity),
+    ) {
+        (Some(pos), Some(vel), Some(hitbox), Some(player)) => (pos.0, vel.0, *hitbox, *player),
+        _ => return Input::default(),
+    };
+
+    let rect_of = |pos: Point, hitbox: &Hitbox| {
+        Rect::new(pos.x + hitbox.0.x, pos.y + hitbox.0.y, hitbox.1, hitbox.2)
+    };
+    let threats: Vec<Threat> = (
+        &world.read_storage::<Bullet>(),
+        &positions,
+        &vels,
+        &hitboxes,
+    )
+        .join()
+        .filter(|(bullet, _, _, _)| bullet.damages_player())
+        .map(|(_, pos, vel, hitbox)| Threat {
+            rect: rect_of(pos.0, hitbox),
+            vel: vel.0,
+        })
+        .chain(
+            (&world.read_storage::<Enemy>(), &positions, &hitboxes)
+                .join()
+                .map(|(_, pos, hitbox)| Threat {
+                    rect: rect_of(pos.0, hitbox),
+                    vel: Vector::new(0.0, 0.0),
+                }),
+        )
+        .collect();
+
+    // the enemy closest to being straight above the player
+    let player_center = player_pos.x + player_hitbox.0.x + player_hitbox.1 / 2.0;
+    let target_x = (&world.read_storage::<Enemy>(), &positions, &hitboxes)
+        .join()
+        .map(|(_, pos, hitbox)| pos.0.x + hitbox.0.x + hitbox.1 / 2.0)
+        .min_by(|a, b| {
+            (a - player_center)
+                .abs()
+                .total_cmp(&(b - player_center).abs())
+        });
+
+    let path = |(up, down, left, right): (bool, bool, bool, bool)| {
+        let input = Input {
+            up,
+            down,
+            left,
+            right,
+            shoot: true,
+            focus: false,
+            deflect: false,
+        };
+        predict_path(player_pos, player_vel, input, player.speed)
+    };
+
+    let (best_move, _) = MOVES
+        .iter()
+        .map(|direction| {
+            let path = path(*direction);
+            let danger: f32 = path
+                .iter()
+                .enumerate()
+                .map(|(i, pos)| {
+                    let frame = i as u32 + 1;
+                    let rect = rect_of(*pos, &player_hitbox);
+                    let rect = Rect::new(
+                        rect.x - MARGIN,
+                        rect.y - MARGIN,
+                        rect.w + MARGIN * 2.0,
+                        rect.h + MARGIN * 2.0,
+                    );
+                    let hits = threats
+                        .iter()
+                        .filter(|threat| threat.at(frame).overlaps(&rect))
+                        .count();
+                    // hits that are further away can still be dodged later
+                    hits as f32 * (LOOKAHEAD + 1 - frame) as f32
+                })
+                .sum();
+
+            let end = path.last().copied().unwrap_or(player_pos);
+            let end_center = end.x + player_hitbox.0.x + player_hitbox.1 / 2.0;
+            let aim = target_x
+                .map(|x| (x - end_center).abs() / SCREEN_WIDTH)
+                .unwrap_or(0.0);
+            let home = (end.y - HOME_Y).abs() / SCREEN_HEIGHT;
+            (*direction, danger * 100.0 + aim + home * 0.5)
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .expect("error choosing a move");
+
+    let (up, down, left, right) = best_move;
+    // the deflector turns on the frame after deflect is let go
+    let deflector_ready = player.deflector_cooldown == 0
+        && player.deflector_timer == 0
+        && player.deflector_held_frames == 0;
+    let about_to_be_hit = || {
+        let path = path(best_move);
+        (1..=DEFLECT_FRAMES).any(|frame| {
+            let rect = rect_of(path[frame as usize - 1], &player_hitbox);
+            threats
+                .iter()
+                .filter(|threat| threat.vel.norm() > 0.0)
+                .any(|threat| threat.at(frame).overlaps(&rect))
+        })
+    };
+
+    Input {
+        up,
+        down,
+        left,
+        right,
+        shoot: true,
+        focus: false,
+        deflect: deflector_ready && about_to_be_hit(),
+    }
+}
+
+// where the player will be for the next LOOKAHEAD frames if it keeps the same input
+fn predict_path(mut pos: Point, mut vel: Vector, input: Input, ship_speed: f32) -> Vec<Point> {
+    (0..LOOKAHEAD)
+        .map(|_| {
+            sim::steer_player(&mut pos, &mut vel, input, ship_speed);
+            pos += vel;
+            pos
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RunReport {
+    pub seed: u64,
+    pub difficulty: Difficulty,
+    // the wave the bot died on, or was on when the run ran out of frames
+    pub wave: u8,
+    pub frames: u32,
+    pub survived: bool,
+    pub hits: HashMap<DamageSource, u32>,
+    pub grazes: u32,
+    pub deflects: u32,
+    // deflects that sent back at least one bullet
+    pub successful_deflects: u32,
+}
+
+// plays one run headless until the bot dies or `max_frames` have passed
+pub fn play(
+    resources_dir: &Path,
+    seed: u64,
+    max_frames: u32,
+    difficulty: Difficulty,
+) -> Result<RunReport, String> {
+    let mut world = sim::headless_world(resources_dir, seed)?;
+    world.insert(difficulty);
+    let starting_hp = sim::difficulty_stats(&world).starting_hp;
+    sim::spawn_player(&mut world, starting_hp);
+    let mut dispatcher = sim::create_dispatcher();
+    dispatcher.setup(&mut world);
+
+    let mut report = RunReport {
+        seed,
+        difficulty,
+        ..RunReport::default()
+    };
+    // frames left for the last deflect to send something back
+    let mut deflect_window: Option<u32> = None;
+
+    while report.frames < max_frames && !world.fetch::<Dead>().0 {
+        let input = input(&world);
+        sim::step(&mut world, &mut dispatcher, input);
+        report.frames += 1;
+
+        let events = world.fetch::<FrameEvents>().clone();
+        events.player_hits.iter().for_each(|source| {
+            *report.hits.entry(*source).or_insert(0) += 1;
+        });
+        report.grazes += events.grazes;
+
+        if input.deflect {
+            report.deflects += 1;
+            let player_entity = world.fetch::<PlayerEntity>().0;
+            let active_frames = world
+                .read_storage::<Player>()
+                .get(player_entity)
+                .map_or(0, |player| player.deflector.active_frames);
+            deflect_window = Some(active_frames + 1);
+        }
+        deflect_window = match deflect_window {
+            Some(_) if events.deflections > 0 => {
+                report.successful_deflects += 1;
+                None
+            }
+            Some(frames) if frames > 1 => Some(frames - 1),
+            _ => None,
+        };
+    }
+
+    report.wave = world.fetch::<CurrentWave>().0;
+    report.survived = !world.fetch::<Dead>().0;
+    Ok(report)
+}
+
+fn source_name(source: &DamageSource) -> String {
+    match source {
+        DamageSource::Bullet(ty) => {
+            // tracking and bouncing bullets carry their frames and bounces left
+            let name = format!("{:?}", ty);
+            name.split('(').next().unwrap_or(&name).to_string()
+        }
+        DamageSource::Enemy(ty) => format!("{:?} (collision)", ty),
+    }
+}
+
+pub fn format_reports(reports: &[RunReport]) -> String {
+    let mut text = String::new();
+    reports.iter().for_each(|report| {
+        writeln!(
+            text,
+            "seed {} on {:?}: {} wave {} after {} frames, {} hits, {} grazes, {}/{} deflects",
+            report.seed,
+            report.difficulty,
+            if report.survived {
+                "survived to"
+            } else {
+                "died on"
+            },
+            report.wave,
+            report.frames,
+            report.hits.values().sum::<u32>(),
+            report.grazes,
+            report.successful_deflects,
+            report.deflects
+        )
+        .expect("error formatting report");
+    });
+    if reports.is_empty() {
+        return text;
+    }
+
+    let mut waves: Vec<u8> = reports.iter().map(|report| report.wave).collect();
+    waves.sort();
+    let mean = waves.iter().map(|wave| f32::from(*wave)).sum::<f32>() / waves.len() as f32;
+    writeln!(
+        text,
+        "\nsurvival wave over {} runs: mean {:.1}, median {}, min {}, max {}",
+        reports.len(),
+        mean,
+        waves[waves.len() / 2],
+        waves[0],
+        waves[waves.len() - 1]
+    )
+    .expect("error formatting report");
+
+    let mut hits: HashMap<String, u32> = HashMap::new();
+    reports
+        .iter()
+        .flat_map(|report| report.hits.iter())
+        .for_each(|(source, count)| *hits.entry(source_name(source)).or_insert(0) += count);
+    let total_hits: u32 = hits.values().sum();
+    let mut hits: Vec<(String, u32)> = hits.into_iter().collect();
+    hits.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then(a_name.cmp(b_name)));
+    writeln!(text, "damage taken: {}", total_hits).expect("error formatting report");
+    hits.iter().for_each(|(name, count)| {
+        writeln!(
+            text,
+            "  {:<24} {:>6} {:>5.1}%",
+            name,
+            count,
+            *count as f32 / total_hits.max(1) as f32 * 100.0
+        )
+        .expect("error formatting report");
+    });
+
+    let deflects: u32 = reports.iter().map(|report| report.deflects).sum();
+    let successful: u32 = reports
+        .iter()
+        .map(|report| report.successful_deflects)
+        .sum();
+    writeln!(
+        text,
+        "deflects: {}, successful {} ({:.1}%)",
+        deflects,
+        successful,
+        successful as f32 / deflects.max(1) as f32 * 100.0
+    )
+    .expect("error formatting report");
+    text
+}
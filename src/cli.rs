@@ -0,0 +1,147 @@
+use ggez::{GameError, GameResult};
+
+use std::convert::TryFrom;
+use std::path::Path;
+
+use crate::ecs::resources::Difficulty;
+
+const USAGE: &str = "usage:
+    ssshmup                                 run the game
+    ssshmup pack-atlas <input dir> <output> pack every png in a directory into <output>.png and <output>.ron
+    ssshmup --profile <output>              run the game and write system timings to <output>.json and <output>.csv on exit
+    ssshmup check-assets [resources dir]    validate the asset manifest and everything it references
+    ssshmup bench [frames] [seed] [output]  play scripted frames headless and report p50/p99 times per system
+    ssshmup bot [runs] [seed] [max frames] [difficulty]
+                                            let a bot play runs headless from consecutive seeds and report how it did
+    ssshmup waves [waves] [seeds] [seed] [difficulty]
+                                            print the enemies and their stats for each wave as csv, for consecutive seeds
+
+difficulty is one of easy, normal, hard or lunatic, normal by default";
+
+pub fn run(command: &str, args: &[String]) -> GameResult {
+    match (command, args) {
+        ("pack-atlas", [input_dir, output]) => {
+            let atlas = crate::atlas::pack(Path::new(input_dir), Path::new(output))
+                .map_err(GameError::ResourceLoadError)?;
+            println!("packed {} frames into {}", atlas.frames.len(), atlas.image);
+            Ok(())
+        }
+        ("check-assets", args) if args.len() <= 1 => {
+            let resources_dir = args.first().map(String::as_str).unwrap_or("resources");
+            let errors = crate::assets::validate(Path::new(resources_dir));
+            if errors.is_empty() {
+                println!("all assets are ok");
+                Ok(())
+            } else {
+                errors.iter().for_each(|error| println!("{}", error));
+                Err(GameError::ResourceLoadError(format!(
+                    "{} assets failed validation",
+                    errors.len()
+                )))
+            }
+        }
+        ("bench", args) if args.len() <= 3 => {
+            let number = |i: usize, default: u64| number_arg(args, i, default);
+            let frames = number(0, crate::profiler::DEFAULT_CAPACITY as u64)?;
+            let frames = u32::try_from(frames).map_err(|_| {
+                GameError::ConfigError(format!(
+                    "{} frames is too many, the most is {}",
+                    frames,
+                    u32::MAX
+                ))
+            })?;
+            let seed = number(1, 0)?;
+
+            let profiler = crate::sim::bench(Path::new(crate::RESOURCES_DIR), frames, seed)
+                .map_err(GameError::ResourceLoadError)?;
+            println!("{} frames with seed {}", frames, seed);
+            print!(
+                "{}",
+                crate::profiler::format_summaries(&profiler.summarize())
+            );
+            if let Some(output) = args.get(2) {
+                profiler
+                    .export(Path::new(output))
+                    .map_err(GameError::ResourceLoadError)?;
+            }
+            Ok(())
+        }
+        ("bot", args) if args.len() <= 4 => {
+            let runs = number_arg(args, 0, 20)?;
+            let first_seed = number_arg(args, 1, 0)?;
+            let max_frames = number_arg(args, 2, u64::from(crate::bot::DEFAULT_MAX_FRAMES))? as u32;
+            let difficulty = difficulty_arg(args, 3)?;
+
+            let last_seed = first_seed.checked_add(runs).ok_or_else(|| {
+                GameError::ConfigError(format!(
+                    "{} runs from seed {} goes past the last seed, {}",
+                    runs,
+                    first_seed,
+                    u64::MAX
+                ))
+            })?;
+
+            let reports = (first_seed..last_seed)
+                .map(|seed| {
+                    crate::bot::play(
+                        Path::new(crate::RESOURCES_DIR),
+                        seed,
+                        max_frames,
+                        difficulty,
+                    )
+                })
+                .collect::<Result<Vec<_>, String>>()
+                .map_err(GameError::ResourceLoadError)?;
+            print!("{}", crate::bot::format_reports(&reports));
+            Ok(())
+        }
+        ("waves", args) if args.len() <= 4 => {
+            let waves = number_arg(args, 0, 30)?.min(u64::from(u8::MAX)) as u8;
+            let seeds = number_arg(args, 1, 10)?;
+            let first_seed = number_arg(args, 2, 0)?;
+            let difficulty = difficulty_arg(args, 3)?;
+
+            let balance = crate::assets::balance_from_file(Path::new(crate::RESOURCES_DIR))
+                .map_err(GameError::ResourceLoadError)?;
+
+            let last_seed = first_seed.checked_add(seeds).ok_or_else(|| {
+                GameError::ConfigError(format!(
+                    "{} seeds from seed {} goes past the last seed, {}",
+                    seeds,
+                    first_seed,
+                    u64::MAX
+                ))
+            })?;
+
+            let stats: Vec<_> = (first_seed..last_seed)
+                .flat_map(|seed| crate::waves::wave_stats(waves, seed, difficulty, &balance))
+                .collect();
+            print!("{}", crate::waves::csv(&stats));
+            Ok(())
+        }
+        _ => {
+            println!("{}", USAGE);
+            Err(GameError::ConfigError(format!(
+                "unknown command or arguments: {}",
+                command
+            )))
+        }
+    }
+}
+
+fn number_arg(args: &[String], i: usize, default: u64) -> GameResult<u64> {
+    match args.get(i) {
+        Some(arg) => arg
+            .parse::<u64>()
+            .map_err(|_| GameError::ConfigError(format!("{} isn't a number", arg))),
+        None => Ok(default),
+    }
+}
+
+fn difficulty_arg(args: &[String], i: usize) -> GameResult<Difficulty> {
+    match args.get(i) {
+        Some(arg) => Difficulty::from_name(arg)
+            .ok_or_else(|| GameError::ConfigError(format!("{} isn't a difficulty", arg))),
+        None => Ok(Difficulty::default()),
+    }
+}
@@ -0,0 +1,377 @@
+use std::convert::TryFrom;
+
+use ggez::{
+    graphics::{self, Color, DrawMode, DrawParam, Font, Mesh, Rect, Scale, Text},
+    input::keyboard::KeyCode,
+    Context, GameResult,
+};
+use specs::prelude::*;
+
+use crate::ecs::components::*;
+use crate::ecs::resources::*;
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// lines of console output kept on screen
+const CONSOLE_LINES: usize = 8;
+const TEXT_SIZE: f32 = 20.0;
+
+const HELP: &str = "commands: spawn <enemy> <x> <y>, wave <n>, god, \
+                    give <hp|score|deflector> [amount], timescale <0-1>, help";
+
+// F3 toggles the overlay and ` opens the console. only available in debug builds, or with
+// the debug_tools feature
+#[derive(Clone, Debug)]
+pub struct DebugTools {
+    pub overlay_visible: bool,
+    pub console_open: bool,
+    input: String,
+    output: Vec<String>,
+    // previous commands, up and down go through them
+    commands: Vec<String>,
+    recalled: Option<usize>,
+    // the game runs a frame every 1 / time_scale updates
+    time_scale: f32,
+    time_accumulator: f32,
+}
+
+impl Default for DebugTools {
+    fn default() -> Self {
+        DebugTools {
+            overlay_visible: false,
+            console_open: false,
+            input: String::new(),
+            output: vec![HELP.to_string()],
+            commands: Vec::new(),
+            recalled: None,
+            time_scale: 1.0,
+            time_accumulator: 0.0,
+        }
+    }
+}
+
+impl DebugTools {
+    pub fn enabled() -> bool {
+        cfg!(debug_assertions) || cfg!(feature = "debug_tools")
+    }
+
+    // whether the game should run this frame, for slow motion
+    pub fn step(&mut self) -> bool {
+        self.time_accumulator += self.time_scale;
+        if self.time_accumulator >= 1.0 {
+            self.time_accumulator -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    // returns true if the key was used, the console takes every key while it's open
+    pub fn key_down(&mut self, world: &mut World, keycode: KeyCode) -> bool {
+        if !self.console_open {
+            return match keycode {
+                KeyCode::F3 => {
+                    self.overlay_visible = !self.overlay_visible;
+                    true
+                }
+                KeyCode::Grave => {
+                    self.console_open = true;
+                    true
+                }
+                _ => false,
+            };
+        }
+
+        match keycode {
+            KeyCode::Grave | KeyCode::Escape => self.console_open = false,
+            KeyCode::Back => {
+                self.input.pop();
+            }
+            KeyCode::Up | KeyCode::Down if !self.commands.is_empty() => {
+                let last = self.commands.len() - 1;
+                let recalled = match (keycode, self.recalled) {
+                    (KeyCode::Up, None) => last,
+                    (KeyCode::Up, Some(i)) => i.saturating_sub(1),
+                    (_, Some(i)) if i < last => i + 1,
+                    _ => last,
+                };
+                self.recalled = Some(recalled);
+                self.input = self.commands[recalled].clone();
+            }
+            KeyCode::Return | KeyCode::NumpadEnter => {
+                let line = std::mem::take(&mut self.input);
+                self.recalled = None;
+                if !line.trim().is_empty() {
+                    let result = self.run_command(world, &line);
+                    self.output.push(format!("> {}", line));
+                    self.output
+                        .push(result.unwrap_or_else(|e| format!("error: {}", e)));
+                    self.commands.push(line);
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    pub fn text_input(&mut self, character: char) {
+        if self.console_open && !character.is_control() && character != '`' {
+            self.input.push(character);
+        }
+    }
+
+    fn run_command(&mut self, world: &mut World, line: &str) -> Result<String, String> {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let number = |i: usize| -> Result<f32, String> {
+            let arg = args
+                .get(i)
+                .ok_or_else(|| format!("missing argument {}", i))?;
+            arg.parse::<f32>()
+                .map_err(|_| format!("{} isn't a number", arg))
+        };
+        // amounts and wave numbers, anything negative or too big is turned down
+        let whole = |i: usize| -> Result<u32, String> {
+            let arg = args
+                .get(i)
+                .ok_or_else(|| format!("missing argument {}", i))?;
+            arg.parse::<u32>()
+                .map_err(|_| format!("{} isn't a whole number from 0 to {}", arg, u32::MAX))
+        };
+
+        match args[0] {
+            "help" => Ok(HELP.to_string()),
+            "spawn" => {
+                let name = args.get(1).ok_or("missing enemy type")?;
+                let ty = EnemyType::from_name(name).ok_or_else(|| {
+                    let names: Vec<String> =
+                        ENEMY_TYPES.iter().map(|ty| format!("{:?}", ty)).collect();
+                    format!("unknown enemy {}, try one of {}", name, names.join(", "))
+                })?;
+                let (x, y) = (number(2)?, number(3)?);
+                let enemy = new_enemy(
+                    ty,
+                    Point::new(x, y),
+                    MovementType::horizontal(x, 75.0, 1.0),
+                    &world.fetch::<Balance>(),
+                    &mut world.fetch_mut::<GameRng>().0,
+                );
+                create_enemy(world, enemy);
+                Ok(format!("spawned {:?} at {}, {}", ty, x, y))
+            }
+            "wave" => {
+                let wave = u8::try_from(whole(1)?)
+                    .ok()
+                    .filter(|wave| *wave > 0)
+                    .ok_or("waves go from 1 to 255")?;
+                {
+                    // the next update sees no enemies and spawns the wave straight away
+                    let entities = world.entities();
+                    let enemies = world.read_storage::<Enemy>();
+                    let bullets = world.read_storage::<Bullet>();
+                    (&entities, (&enemies).maybe(), (&bullets).maybe())
+                        .join()
+                        .filter(|(_, enemy, bullet)| enemy.is_some() || bullet.is_some())
+                        .for_each(|(entity, _, _)| {
+                            entities
+                                .delete(entity)
+                                .expect("error deleting enemy or bullet");
+                        });
+                }
+                world.maintain();
+                world.insert(CurrentWave(wave - 1));
+                world.insert(FramesToNextWave(0));
+                Ok(format!("skipping to wave {}", wave))
+            }
+            "god" => {
+                let god_mode = &mut world.fetch_mut::<GodMode>().0;
+                *god_mode = !*god_mode;
+                Ok(format!("god mode {}", if *god_mode { "on" } else { "off" }))
+            }
+            "give" => {
+                // there are no powerups yet, so these are the things that can be given
+                let what = args.get(1).ok_or("missing what to give")?;
+                if world.fetch::<Dead>().0 {
+                    return Err("the player is dead".to_string());
+                }
+                let player_entity = world.fetch::<PlayerEntity>().0;
+                match *what {
+                    "hp" => {
+                        let amount = if args.len() > 2 { whole(2)? } else { 1 };
+                        let mut hp_storage = world.write_storage::<HP>();
+                        let hp = hp_storage
+                            .get_mut(player_entity)
+                            .ok_or("the player has no hp")?;
+                        hp.remaining = hp.remaining.saturating_add(amount);
+                        Ok(format!("hp is now {}", hp.remaining))
+                    }
+                    "score" => {
+                        let amount = if args.len() > 2 { whole(2)? } else { 1000 };
+                        let score = &mut world.fetch_mut::<Score>();
+                        score.add(amount);
+                        Ok(format!("score is now {}", score.current))
+                    }
+                    "deflector" => {
+                        let mut players = world.write_storage::<Player>();
+                        let player = players
+                            .get_mut(player_entity)
+                            .ok_or("the player has no deflector")?;
+                        player.deflector_cooldown = 0;
+                        Ok("deflector recharged".to_string())
+                    }
+                    _ => Err(format!("can't give {}, try hp, score or deflector", what)),
+                }
+            }
+            "timescale" => {
+                let time_scale = number(1)?;
+                if time_scale <= 0.0 || time_scale > 1.0 {
+                    return Err("the time scale has to be above 0 and at most 1".to_string());
+                }
+                self.time_scale = time_scale;
+                self.time_accumulator = 0.0;
+                Ok(format!("time scale is now {}", time_scale))
+            }
+            command => Err(format!("unknown command {}, {}", command, HELP)),
+        }
+    }
+
+    // `visible` is the part of the window in game coordinates
+    pub fn draw(&self, ctx: &mut Context, world: &World, visible: Rect) -> GameResult {
+        let font = world.fetch::<GameFont>().0;
+        if self.overlay_visible {
+            let text = make_text(&overlay_lines(ctx, world).join("\n"), font);
+            draw_boxed(ctx, &text, [visible.x + 8.0, visible.y + 8.0])?;
+        }
+
+        if self.console_open {
+            let skip = self.output.len().saturating_sub(CONSOLE_LINES);
+            let mut lines: Vec<&str> = self.output.iter().skip(skip).map(String::as_str).collect();
+            let input = format!("> {}_", self.input);
+            lines.push(&input);
+
+            let text = make_text(&lines.join("\n"), font);
+            let height = text.height(ctx) as f32;
+            draw_boxed(
+                ctx,
+                &text,
+                [visible.x + 8.0, visible.y + visible.h - height - 16.0],
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn overlay_lines(ctx: &mut Context, world: &World) -> Vec<String> {
+    fn count<C: Component>(world: &World) -> usize {
+        world.read_storage::<C>().join().count()
+    }
+
+    let mut lines = vec![
+        format!("fps: {:.0}", ggez::timer::fps(ctx)),
+        format!("entities: {}", world.entities().join().count()),
+    ];
+
+    let counts = [
+        ("Position", count::<Position>(world)),
+        ("Velocity", count::<Velocity>(world)),
+        ("Sprite", count::<Sprite>(world)),
+        ("AnimatedSprite", count::<AnimatedSprite>(world)),
+        ("Hitbox", count::<Hitbox>(world)),
+        ("HP", count::<HP>(world)),
+        ("Enemy", count::<Enemy>(world)),
+        ("Bullet", count::<Bullet>(world)),
+        ("Deflected", count::<Deflected>(world)),
+        ("Grazing", count::<Grazing>(world)),
+        ("Grazed", count::<Grazed>(world)),
+        ("Particle", count::<Particle>(world)),
+        ("ParticleEmitter", count::<ParticleEmitter>(world)),
+        ("Star", count::<Star>(world)),
+    ];
+    lines.extend(
+        counts
+            .iter()
+            .map(|(name, count)| format!("  {}: {}", name, count)),
+    );
+
+    let playfield = Rect::new(0.0, 0.0, SCREEN_WIDTH, SCREEN_HEIGHT);
+    let bullets_on_screen = (
+        &world.read_storage::<Bullet>(),
+        &world.read_storage::<Position>(),
+    )
+        .join()
+        .filter(|(_, pos)| playfield.contains(pos.0))
+        .count();
+    lines.push(format!("bullets on screen: {}", bullets_on_screen));
+
+    // enemies count double against the budget, see WaveCalcSys
+    let wave = world.fetch::<CurrentWave>().0;
+    let difficulty = *world.fetch::<Difficulty>();
+    let rank = *world.fetch::<Rank>();
+    let balance = world.fetch::<Balance>();
+    let remaining: u16 = world
+        .read_storage::<Enemy>()
+        .join()
+        .map(|enemy| balance.enemy(enemy.ty).cost * 2)
+        .sum();
+    lines.push(format!(
+        "wave {} on {:?}: budget {}, remaining {}",
+        wave,
+        difficulty,
+        rank.budget(balance.wave_budget(wave, difficulty)),
+        remaining
+    ));
+    if rank.enabled {
+        lines.push(format!(
+            "rank {:.2} ({:+.2} last wave), this wave: {} hits, {} deflected, {} frames",
+            rank.value, rank.last_change, rank.wave.hits, rank.wave.deflections, rank.wave.frames
+        ));
+    } else {
+        lines.push("rank off".to_string());
+    }
+    if world.fetch::<GodMode>().0 {
+        lines.push("god mode".to_string());
+    }
+
+    let mut timings: Vec<(&'static str, std::time::Duration)> = world
+        .fetch::<SystemTimings>()
+        .0
+        .lock()
+        .expect("error locking system timings")
+        .last_frame()
+        .map(|frame| {
+            frame
+                .samples
+                .iter()
+                .map(|sample| (sample.name, sample.duration))
+                .collect()
+        })
+        .unwrap_or_default();
+    timings.sort_by(|(_, a), (_, b)| b.cmp(a));
+    let total: std::time::Duration = timings.iter().map(|(_, time)| *time).sum();
+    lines.push(format!("systems: {:.3}ms", total.as_secs_f64() * 1000.0));
+    lines.extend(
+        timings
+            .iter()
+            .map(|(name, time)| format!("  {}: {:.3}ms", name, time.as_secs_f64() * 1000.0)),
+    );
+
+    lines
+}
+
+fn make_text(text: &str, font: Font) -> Text {
+    let mut text = Text::new(text);
+    text.set_font(font, Scale::uniform(TEXT_SIZE));
+    text
+}
+
+// text over a dark box so that it's readable over the game
+fn draw_boxed(ctx: &mut Context, text: &Text, dest: [f32; 2]) -> GameResult {
+    let (w, h) = text.dimensions(ctx);
+    let background = Mesh::new_rectangle(
+        ctx,
+        DrawMode::fill(),
+        Rect::new(dest[0] - 4.0, dest[1] - 4.0, w as f32 + 8.0, h as f32 + 8.0),
+        Color::new(0.0, 0.0, 0.0, 0.7),
+    )?;
+    graphics::draw(ctx, &background, DrawParam::new())?;
+    graphics::draw(ctx, text, DrawParam::new().dest(dest))
+}
@@ -1,6 +1,7 @@
 use ggez::graphics::{Color, Image};
 use ggez::nalgebra::{Point2, Vector2};
 
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 use specs::Component;
 
@@ -33,14 +34,18 @@ impl Default for Velocity {
 #[derive(Clone, Debug, Component)]
 #[storage(VecStorage)]
 pub enum Sprite {
-    Img(Image),
-    SpriteSheetInstance(Arc<Mutex<SpriteSheet>>, u8),
+    // the key of an image in the Sprites resource
+    Img(String),
+    SpriteSheetInstance(Arc<Mutex<SpriteSheet>>, usize),
 }
 
 #[derive(Clone, Debug, PartialEq, Component)]
 #[storage(DenseVecStorage)]
 pub struct AnimatedSprite {
-    pub spritesheet: Image,
+    // the animation's key in the asset manifest
+    pub key: String,
+    // None when running headless, the animation still plays but isn't drawn
+    pub spritesheet: Option<Image>,
     pub num_frames: u8,
     pub spritesheet_width: u8,
     pub current_frame: u8,
@@ -48,8 +53,15 @@ pub struct AnimatedSprite {
 }
 
 impl AnimatedSprite {
-    pub fn new(spritesheet: Image, num_frames: u8, spritesheet_width: u8, temporary: bool) -> Self {
+    pub fn new(
+        key: &str,
+        spritesheet: Option<Image>,
+        num_frames: u8,
+        spritesheet_width: u8,
+        temporary: bool,
+    ) -> Self {
         AnimatedSprite {
+            key: key.to_string(),
             spritesheet,
             num_frames,
             spritesheet_width,
@@ -69,6 +81,158 @@ impl AnimatedSprite {
 #[storage(NullStorage)]
 pub struct Explosion;
 
+// particles are drawn as plain colored squares, their
+// color and size are interpolated over their lifetime
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+#[storage(VecStorage)]
+pub struct Particle {
+    pub age: u16,
+    pub lifetime: u16,
+    pub drag: f32,
+    pub start_color: Color,
+    pub end_color: Color,
+    pub start_size: f32,
+    pub end_size: f32,
+}
+
+impl Particle {
+    fn progress(&self) -> f32 {
+        self.age as f32 / self.lifetime as f32
+    }
+
+    pub fn color(&self) -> Color {
+        let t = self.progress();
+        let (start, end) = (self.start_color, self.end_color);
+        Color::new(
+            start.r + (end.r - start.r) * t,
+            start.g + (end.g - start.g) * t,
+            start.b + (end.b - start.b) * t,
+            start.a + (end.a - start.a) * t,
+        )
+    }
+
+    pub fn size(&self) -> f32 {
+        self.start_size + (self.end_size - self.start_size) * self.progress()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ParticleEffect {
+    Debris,
+    Thruster,
+    Spark,
+    Trail,
+    Graze,
+}
+
+impl ParticleEffect {
+    // how many particles get spawned every time the effect is emitted
+    pub fn burst_size(self) -> usize {
+        match self {
+            ParticleEffect::Debris => 14,
+            ParticleEffect::Spark => 8,
+            ParticleEffect::Graze => 4,
+            ParticleEffect::Thruster | ParticleEffect::Trail => 1,
+        }
+    }
+
+    pub fn new_particle(self, pos: Point) -> (Position, Velocity, Particle) {
+        use rand::Rng;
+
+        let mut rng = rand::thread_rng();
+        let angle = rng.gen_range(0.0, std::f32::consts::PI * 2.0);
+        let random_direction = Vector::new(angle.cos(), angle.sin());
+
+        let (vel, lifetime, drag, colors, sizes) = match self {
+            ParticleEffect::Debris => (
+                random_direction * rng.gen_range(1.0, 4.0),
+                rng.gen_range(25, 45),
+                0.93,
+                (
+                    Color::new(1.0, 0.6, 0.2, 1.0),
+                    Color::new(0.5, 0.1, 0.1, 0.0),
+                ),
+                (6.0, 2.0),
+            ),
+            ParticleEffect::Spark => (
+                random_direction * rng.gen_range(3.0, 6.0),
+                rng.gen_range(8, 14),
+                0.85,
+                (
+                    Color::new(1.0, 1.0, 0.7, 1.0),
+                    Color::new(1.0, 0.8, 0.2, 0.0),
+                ),
+                (3.0, 1.0),
+            ),
+            ParticleEffect::Graze => (
+                random_direction * rng.gen_range(2.0, 4.0),
+                rng.gen_range(6, 10),
+                0.85,
+                (
+                    Color::new(0.8, 0.9, 1.0, 1.0),
+                    Color::new(0.4, 0.6, 1.0, 0.0),
+                ),
+                (2.0, 0.5),
+            ),
+            ParticleEffect::Thruster => (
+                Vector::new(rng.gen_range(-0.4, 0.4), rng.gen_range(2.0, 3.5)),
+                rng.gen_range(10, 16),
+                1.0,
+                (
+                    Color::new(0.6, 0.8, 1.0, 0.8),
+                    Color::new(0.2, 0.3, 1.0, 0.0),
+                ),
+                (4.0, 1.0),
+            ),
+            ParticleEffect::Trail => (
+                random_direction * 0.3,
+                rng.gen_range(12, 20),
+                1.0,
+                (
+                    Color::new(0.6, 1.0, 0.6, 0.6),
+                    Color::new(0.2, 0.6, 0.2, 0.0),
+                ),
+                (5.0, 2.0),
+            ),
+        };
+
+        (
+            Position(pos),
+            Velocity(vel),
+            Particle {
+                age: 0,
+                lifetime,
+                drag,
+                start_color: colors.0,
+                end_color: colors.1,
+                start_size: sizes.0,
+                end_size: sizes.1,
+            },
+        )
+    }
+}
+
+// continuously emits particles relative to the entity's position
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+#[storage(DenseVecStorage)]
+pub struct ParticleEmitter {
+    pub effect: ParticleEffect,
+    pub offset: Vector,
+    pub interval: u8,
+    pub timer: u8,
+}
+
+impl ParticleEmitter {
+    pub fn new(effect: ParticleEffect, offset: Vector, interval: u8) -> Self {
+        ParticleEmitter {
+            effect,
+            offset,
+            interval,
+            timer: 0,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Component)]
 #[storage(VecStorage)]
 pub struct ColorRect {
@@ -99,7 +263,7 @@ pub struct BounceInfo {
     pub used_bounces: u8,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum BulletType {
     PlayerBullet,
     BasicBullet,
@@ -134,7 +298,9 @@ impl Bullet {
     }
 }
 
-pub type BulletTuple = (Position, Hitbox, Velocity, Bullet, u8);
+// the last element is the name of the bullet's frame in the bullet spritesheet, which also has
+// the bullet's hitbox
+pub type BulletTuple = (Position, Velocity, Bullet, &'static str);
 pub fn new_bullet(ty: BulletType, pos: Point, vel: Vector, damages_who: DamagesWho) -> BulletTuple {
     let damage = match ty {
         BulletType::PlayerBullet => 3,
@@ -145,21 +311,12 @@ pub fn new_bullet(ty: BulletType, pos: Point, vel: Vector, damages_who: DamagesW
         | BulletType::BouncingBullet(_) => 1,
     };
 
-    let sprite_index = match ty {
-        BulletType::BasicBullet => 0,
-        BulletType::AimedBullet | BulletType::PlayerBullet => 1,
-        BulletType::PredictBullet => 2,
-        BulletType::TrackingBullet(_) => 3,
-        BulletType::BouncingBullet(_) => 4,
-    };
-
-    let (offset, width, height) = match ty {
-        BulletType::PlayerBullet
-        | BulletType::BasicBullet
-        | BulletType::AimedBullet
-        | BulletType::PredictBullet
-        | BulletType::TrackingBullet(_)
-        | BulletType::BouncingBullet(_) => (Point::new(5.0, 5.0), 15.0, 15.0),
+    let sprite_name = match ty {
+        BulletType::BasicBullet => "basic",
+        BulletType::AimedBullet | BulletType::PlayerBullet => "aimed",
+        BulletType::PredictBullet => "predict",
+        BulletType::TrackingBullet(_) => "tracking",
+        BulletType::BouncingBullet(_) => "bouncing",
     };
 
     let bullet = Bullet {
@@ -169,16 +326,10 @@ pub fn new_bullet(ty: BulletType, pos: Point, vel: Vector, damages_who: DamagesW
     };
 
     let pos: Point = [pos.x, pos.y - 16.0].into();
-    (
-        Position(pos),
-        Hitbox(offset, width, height),
-        Velocity(vel),
-        bullet,
-        sprite_index,
-    )
+    (Position(pos), Velocity(vel), bullet, sprite_name)
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum EnemyType {
     BasicEnemy,
     BasicEnemy2,
@@ -189,6 +340,28 @@ pub enum EnemyType {
     BounceEnemy,
 }
 
+// in the order the wave generator prefers them when they're equally useful
+pub const ENEMY_TYPES: [EnemyType; 7] = [
+    EnemyType::BasicEnemy,
+    EnemyType::BasicEnemy2,
+    EnemyType::AimEnemy,
+    EnemyType::AimEnemy2,
+    EnemyType::PredictEnemy,
+    EnemyType::TrackingEnemy,
+    EnemyType::BounceEnemy,
+];
+
+impl EnemyType {
+    // case insensitive, either the full name or without "Enemy", e.g. "aim2"
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        ENEMY_TYPES.iter().copied().find(|ty| {
+            let full_name = format!("{:?}", ty).to_lowercase();
+            full_name == name || full_name.replacen("enemy", "", 1) == name
+        })
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum MovementType {
     HLine(std::ops::Range<f32>, f32),
@@ -215,17 +388,26 @@ pub struct Enemy {
     pub reload_speed: u32,
 }
 
-pub type EnemyTuple = (Position, Velocity, Enemy, HP, Hitbox, u8);
-pub fn new_enemy(ty: EnemyType, pos: Point, movement: MovementType) -> EnemyTuple {
+// the last element is the name of the enemy's frame in the enemy spritesheet, which also has
+// the enemy's hitbox
+pub type EnemyTuple = (Position, Velocity, Enemy, HP, &'static str);
+pub fn new_enemy(
+    ty: EnemyType,
+    pos: Point,
+    movement: MovementType,
+    balance: &Balance,
+    rng: &mut impl rand::Rng,
+) -> EnemyTuple {
     let pos = Position(pos);
-    let (hp, size, bullet_type, reload_speed) = match ty {
-        EnemyType::BasicEnemy => (3, (55.0, 43.0), BulletType::BasicBullet, 180),
-        EnemyType::BasicEnemy2 => (5, (55.0, 43.0), BulletType::BasicBullet, 90),
-        EnemyType::AimEnemy => (3, (55.0, 43.0), BulletType::AimedBullet, 180),
-        EnemyType::PredictEnemy => (3, (55.0, 43.0), BulletType::PredictBullet, 90),
-        EnemyType::TrackingEnemy => (3, (55.0, 43.0), BulletType::TrackingBullet(7 * 30), 180),
-        EnemyType::AimEnemy2 => (5, (55.0, 43.0), BulletType::AimedBullet, 90),
-        EnemyType::BounceEnemy => (3, (55.0, 43.0), BulletType::BouncingBullet(2), 180),
+    let EnemyStats {
+        hp, reload_speed, ..
+    } = balance.enemy(ty);
+    let bullet_type = match ty {
+        EnemyType::BasicEnemy | EnemyType::BasicEnemy2 => BulletType::BasicBullet,
+        EnemyType::AimEnemy | EnemyType::AimEnemy2 => BulletType::AimedBullet,
+        EnemyType::PredictEnemy => BulletType::PredictBullet,
+        EnemyType::TrackingEnemy => BulletType::TrackingBullet(7 * 30),
+        EnemyType::BounceEnemy => BulletType::BouncingBullet(2),
     };
 
     let vel = match movement {
@@ -233,18 +415,16 @@ pub fn new_enemy(ty: EnemyType, pos: Point, movement: MovementType) -> EnemyTupl
         MovementType::VLine(_, speed) => [0.0, speed].into(),
     };
 
-    let sprite_index = match ty {
-        EnemyType::BasicEnemy => 0,
-        EnemyType::AimEnemy => 1,
-        EnemyType::PredictEnemy => 2,
-        EnemyType::TrackingEnemy => 3,
-        EnemyType::BasicEnemy2 => 4,
-        EnemyType::AimEnemy2 => 5,
-        EnemyType::BounceEnemy => 7,
+    let sprite_name = match ty {
+        EnemyType::BasicEnemy => "basic",
+        EnemyType::AimEnemy => "aim",
+        EnemyType::PredictEnemy => "predict",
+        EnemyType::TrackingEnemy => "tracking",
+        EnemyType::BasicEnemy2 => "basic2",
+        EnemyType::AimEnemy2 => "aim2",
+        EnemyType::BounceEnemy => "bounce",
     };
 
-    use rand::Rng;
-    let mut rng = rand::thread_rng();
     let reload_offset = rng.gen_range(0, reload_speed / 2);
 
     (
@@ -258,13 +438,25 @@ pub fn new_enemy(ty: EnemyType, pos: Point, movement: MovementType) -> EnemyTupl
             reload_speed,
         },
         HP::new(hp),
-        Hitbox([21.0, 32.0].into(), size.0, size.1),
-        sprite_index,
+        sprite_name,
+    )
+}
+
+// looks up a frame of a spritesheet by name, along with the hitbox from the atlas
+pub fn spritesheet_frame(spritesheet: &Arc<Mutex<SpriteSheet>>, name: &str) -> (Sprite, Hitbox) {
+    let (index, hitbox) = {
+        let spritesheet = spritesheet.lock().expect("error locking spritesheet");
+        let index = spritesheet.frame_index(name);
+        (index, spritesheet.frame_hitbox(index))
+    };
+
+    (
+        Sprite::SpriteSheetInstance(spritesheet.clone(), index),
+        hitbox,
     )
 }
 
-// never used but useful for manually spawning enemies
-#[allow(dead_code)]
+// used by spawn_waves, and by the debug console to spawn enemies by hand
 pub fn create_enemy(world: &mut World, enemy: EnemyTuple) -> Entity {
     let spritesheet = {
         let spritesheets = world.fetch::<SpriteSheets>();
@@ -274,6 +466,7 @@ pub fn create_enemy(world: &mut World, enemy: EnemyTuple) -> Entity {
             .expect("error getting spritesheet")
             .clone()
     };
+    let (sprite, hitbox) = spritesheet_frame(&spritesheet, enemy.4);
 
     world
         .create_entity()
@@ -281,22 +474,90 @@ pub fn create_enemy(world: &mut World, enemy: EnemyTuple) -> Entity {
         .with(enemy.1)
         .with(enemy.2)
         .with(enemy.3)
-        .with(enemy.4)
-        .with(Sprite::SpriteSheetInstance(spritesheet, enemy.5))
+        .with(hitbox)
+        .with(sprite)
         .build()
 }
 
+// a ship's deflector. holding deflect charges it up a level every `level_frames`, and letting go
+// turns it on. every level past the first reaches `level_radius` further out, makes deflected
+// bullets hit harder and adds `level_cooldown` frames to the cooldown
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Deflector {
+    pub active_frames: u32,
+    pub reload_frames: u32,
+    pub level_frames: u32,
+    pub max_level: u32,
+    pub level_radius: f32,
+    pub level_cooldown: u32,
+}
+
+impl Default for Deflector {
+    fn default() -> Self {
+        Deflector {
+            active_frames: 6,
+            reload_frames: 120,
+            level_frames: 30,
+            max_level: 3,
+            level_radius: 18.0,
+            level_cooldown: 45,
+        }
+    }
+}
+
+// how the player's bullets leave the ship
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FirePattern {
+    Single,
+    // two side by side, closer together while focusing
+    Twin,
+    // three in a fan, or side by side while focusing
+    Spread,
+}
+
+impl FirePattern {
+    // each bullet's offset from the gun and angle from straight up, in radians
+    pub fn shots(self, focused: bool) -> Vec<(Vector, f32)> {
+        match self {
+            FirePattern::Single => vec![(Vector::new(0.0, 0.0), 0.0)],
+            FirePattern::Twin if focused => {
+                vec![(Vector::new(-4.0, 0.0), 0.0), (Vector::new(4.0, 0.0), 0.0)]
+            }
+            FirePattern::Twin => vec![(Vector::new(-8.0, 0.0), 0.0), (Vector::new(8.0, 0.0), 0.0)],
+            FirePattern::Spread if focused => vec![
+                (Vector::new(-10.0, 0.0), 0.0),
+                (Vector::new(0.0, 0.0), 0.0),
+                (Vector::new(10.0, 0.0), 0.0),
+            ],
+            FirePattern::Spread => vec![
+                (Vector::new(0.0, 0.0), -0.25),
+                (Vector::new(0.0, 0.0), 0.0),
+                (Vector::new(0.0, 0.0), 0.25),
+            ],
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Component)]
 #[storage(HashMapStorage)]
 pub struct Player {
     pub bullet_type: BulletType,
+    pub fire_pattern: FirePattern,
+    // where bullets leave from, relative to the player's position
+    pub gun: Vector,
+    // multiplies how fast the player moves
+    pub speed: f32,
     pub reload_speed: u32,
     pub reload_timer: u32,
-
-    pub deflector_frames: u32,
+    // while focusing, shots go straight up quicker and closer together
+    pub focused: bool,
+
+    pub deflector: Deflector,
+    // how long deflect has been held, 0 when it isn't charging
+    pub deflector_held_frames: u32,
+    // the level the deflector was let go at
+    pub deflector_level: u32,
     pub deflector_timer: u32,
-
-    pub deflector_reload_frames: u32,
     pub deflector_cooldown: u32,
 }
 
@@ -304,18 +565,35 @@ impl Default for Player {
     fn default() -> Self {
         Player {
             bullet_type: BulletType::PlayerBullet,
+            fire_pattern: FirePattern::Single,
+            gun: Vector::new(12.0, 5.0),
+            speed: 1.0,
             reload_speed: 12,
             reload_timer: 0,
+            focused: false,
 
-            deflector_frames: 6,
+            deflector: Deflector::default(),
+            deflector_held_frames: 0,
+            deflector_level: 1,
             deflector_timer: 0,
-
-            deflector_reload_frames: 120,
             deflector_cooldown: 0,
         }
     }
 }
 
+impl Player {
+    // the level the deflector would be let go at now
+    pub fn charge_level(&self) -> u32 {
+        (1 + self.deflector_held_frames / self.deflector.level_frames.max(1))
+            .min(self.deflector.max_level)
+    }
+
+    // how far past the hitbox a deflector of `level` reaches
+    pub fn deflector_radius(&self, level: u32) -> f32 {
+        level.saturating_sub(1) as f32 * self.deflector.level_radius
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct PlayerEntity(pub Entity);
 
@@ -325,8 +603,36 @@ impl Default for PlayerEntity {
     }
 }
 
-pub type PlayerTuple = (Position, Velocity, HP, Sprite, Player, Hitbox);
-pub fn new_player(sprite: Image, hp: u32) -> PlayerTuple {
+// the player's sprite keys, DeflectorSys switches between them
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Component)]
+#[storage(HashMapStorage)]
+pub struct ShipSprites {
+    pub normal: String,
+    pub deflector: String,
+    pub cooldown: String,
+}
+
+impl Default for ShipSprites {
+    fn default() -> Self {
+        ShipSprites {
+            normal: "player".to_string(),
+            deflector: "player_deflector".to_string(),
+            cooldown: "player_cooldown".to_string(),
+        }
+    }
+}
+
+pub type PlayerTuple = (
+    Position,
+    Velocity,
+    HP,
+    Sprite,
+    Player,
+    Hitbox,
+    ShipSprites,
+    ParticleEmitter,
+);
+pub fn new_player(hp: u32, ship: &Ship) -> PlayerTuple {
     let pos = Position(
         [
             crate::SCREEN_WIDTH / 2.0 - 25.0,
@@ -336,14 +642,29 @@ pub fn new_player(sprite: Image, hp: u32) -> PlayerTuple {
     );
     let vel = Velocity::default();
     let hp = HP::new(hp);
+    let player = Player {
+        fire_pattern: ship.fire_pattern,
+        gun: Vector::new(ship.gun.0, ship.gun.1),
+        speed: ship.speed,
+        reload_speed: ship.reload_speed,
+        deflector: ship.deflector,
+        ..Player::default()
+    };
+    let (x, y, w, h) = ship.hitbox;
 
     (
         pos,
         vel,
         hp,
-        Sprite::Img(sprite),
-        Player::default(),
-        Hitbox([0.0, 0.0].into(), 45.0, 45.0),
+        Sprite::Img(ship.sprites.normal.clone()),
+        player,
+        Hitbox([x, y].into(), w, h),
+        ship.sprites.clone(),
+        ParticleEmitter::new(
+            ParticleEffect::Thruster,
+            Vector::new(ship.thruster.0, ship.thruster.1),
+            2,
+        ),
     )
 }
 
@@ -356,17 +677,46 @@ pub fn create_player(world: &mut World, player: PlayerTuple) -> Entity {
         .with(player.3)
         .with(player.4)
         .with(player.5)
+        .with(player.6)
+        .with(player.7)
         .build()
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Component, Default)]
-#[storage(NullStorage)]
-pub struct Star;
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+#[storage(DenseVecStorage)]
+pub struct Star {
+    pub stage: usize,
+    pub layer: usize,
+    pub twinkle_phase: f32,
+}
+
+impl Star {
+    pub fn new(stage: usize, layer: usize) -> Self {
+        use rand::Rng;
+
+        Star {
+            stage,
+            layer,
+            twinkle_phase: rand::thread_rng().gen_range(0.0, std::f32::consts::PI * 2.0),
+        }
+    }
+}
 
 #[derive(Clone, Copy, Debug, PartialEq, Component)]
 #[storage(DenseVecStorage)]
 pub struct Hitbox(pub Point, pub f32, pub f32);
 
+// how much hp the bullet gives back to the player when it hits an enemy
+#[derive(Clone, Copy, PartialEq, Eq, Default, Component, Debug)]
+#[storage(DenseVecStorage)]
+pub struct Deflected(pub u32);
+
+// a bullet that is passing close to the player, it's grazed once it gets away without hitting
+#[derive(Clone, Copy, PartialEq, Eq, Default, Component, Debug)]
+#[storage(NullStorage)]
+pub struct Grazing;
+
+// a bullet that already passed close to the player, so it's only grazed once
 #[derive(Clone, Copy, PartialEq, Eq, Default, Component, Debug)]
 #[storage(NullStorage)]
-pub struct Deflected;
+pub struct Grazed;
@@ -1,12 +1,18 @@
 use ggez::audio::SoundData;
-use ggez::graphics::{spritebatch::SpriteBatch, Image};
+use ggez::graphics::{spritebatch::SpriteBatch, Color, Image, Rect};
+
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use crate::atlas::Atlas;
+use crate::audio::SoundConfig;
 use crate::ecs::components::*;
+use crate::profiler::Profiler;
 
 // split into two parts for formatting
 #[derive(Default)]
@@ -18,12 +24,6 @@ pub struct GameFont(pub ggez::graphics::Font);
 #[derive(Clone, Default)]
 pub struct Dead(pub bool);
 
-#[derive(Default)]
-pub struct HPText {
-    pub needs_redraw: bool,
-    pub text: Mutex<ggez::graphics::Text>,
-}
-
 // for use with wave spawning
 #[derive(Clone, Default)]
 pub struct QueuedEnemies(pub Vec<(Point, EnemyType)>);
@@ -34,8 +34,64 @@ pub struct Sprites(pub HashMap<String, Image>);
 
 #[derive(Clone, Debug)]
 pub struct SpriteSheet {
-    pub width: u8,
-    pub batch: SpriteBatch,
+    pub atlas: Atlas,
+    pub image_size: (u16, u16),
+    // None when running headless, the frames still have names and hitboxes but aren't drawn
+    pub batch: Option<SpriteBatch>,
+}
+
+impl SpriteSheet {
+    pub fn new(image: Image, atlas: Atlas) -> Self {
+        SpriteSheet {
+            atlas,
+            image_size: (image.width(), image.height()),
+            batch: Some(SpriteBatch::new(image)),
+        }
+    }
+
+    pub fn headless(atlas: Atlas, image_size: (u16, u16)) -> Self {
+        SpriteSheet {
+            atlas,
+            image_size,
+            batch: None,
+        }
+    }
+
+    // missing frames fall back to the first frame so that a typo doesn't crash the game
+    pub fn frame_index(&self, name: &str) -> usize {
+        self.atlas.frame_index(name).unwrap_or_else(|| {
+            log::warn!("missing spritesheet frame {}", name);
+            0
+        })
+    }
+
+    pub fn src_rect(&self, index: usize) -> Rect {
+        self.atlas.src_rect(index, self.image_size)
+    }
+
+    // sprites are drawn scaled up, so the pixel pivot needs to be scaled too
+    pub fn pivot(&self, index: usize) -> Vector {
+        self.atlas
+            .frames
+            .get(index)
+            .map(|frame| Vector::new(frame.pivot.0, frame.pivot.1) * crate::PIXEL_SCALE)
+            .unwrap_or_else(|| Vector::new(0.0, 0.0))
+    }
+
+    // the atlas' hitbox hint scaled up like the sprite. frames without one can be hit anywhere
+    // in the frame, and the whole image stands in for a missing frame
+    pub fn frame_hitbox(&self, index: usize) -> Hitbox {
+        let (x, y, w, h) = match self.atlas.frames.get(index) {
+            Some(frame) => {
+                frame
+                    .hitbox
+                    .unwrap_or((0.0, 0.0, frame.rect.w as f32, frame.rect.h as f32))
+            }
+            None => (0.0, 0.0, self.image_size.0.into(), self.image_size.1.into()),
+        };
+        let scale = crate::PIXEL_SCALE;
+        Hitbox(Point::new(x * scale, y * scale), w * scale, h * scale)
+    }
 }
 
 #[derive(Clone, Default)]
@@ -47,6 +103,417 @@ pub struct AnimatedSprites(pub HashMap<String, crate::ecs::components::AnimatedS
 #[derive(Clone, Default)]
 pub struct CurrentWave(pub u8);
 
+// the high score is loaded from the save file at startup and saved when the player dies
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Score {
+    pub current: u32,
+    pub high: u32,
+}
+
+impl Score {
+    pub fn add(&mut self, points: u32) {
+        self.current = self.current.saturating_add(points);
+        self.high = self.high.max(self.current);
+    }
+}
+
+// bullets grazed this run
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct GrazeCount(pub u32);
+
+// picked in the options menu and applied when a run starts, high scores are kept for each
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    Lunatic,
+}
+
+pub const DIFFICULTIES: [Difficulty; 4] = [
+    Difficulty::Easy,
+    Difficulty::Normal,
+    Difficulty::Hard,
+    Difficulty::Lunatic,
+];
+
+// how a difficulty changes the game, normal is the game as it was balanced
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DifficultyStats {
+    // multiplies every wave's difficulty budget
+    pub wave_budget: f32,
+    // multiplies the frames between enemy shots, so lower shoots more
+    pub enemy_reload: f32,
+    pub bullet_speed: f32,
+    pub starting_hp: u32,
+    pub respawn_hp: u32,
+    // hp gained at the start of every wave after the first
+    pub wave_heal: u32,
+    // multiplies the ship's deflector cooldown
+    pub deflector_reload: f32,
+}
+
+impl Difficulty {
+    // case insensitive
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        DIFFICULTIES
+            .iter()
+            .copied()
+            .find(|difficulty| format!("{:?}", difficulty).to_lowercase() == name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EnemyStats {
+    pub hp: u32,
+    // frames between shots before the difficulty and rank scale it
+    pub reload_speed: u32,
+    // how much of a wave's difficulty budget the enemy uses up
+    pub cost: u16,
+    // points for killing the enemy, tougher enemies are worth more
+    pub score: u32,
+}
+
+// the numbers the game is balanced around, loaded from balance.ron so that they can be tuned
+// without rebuilding. the default is the same as the file that ships with the game
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Balance {
+    pub difficulties: HashMap<Difficulty, DifficultyStats>,
+    pub enemies: HashMap<EnemyType, EnemyStats>,
+    // the budgets of the first waves, later waves get wave * budget_per_wave + budget_base
+    pub wave_budgets: Vec<u16>,
+    pub budget_per_wave: u16,
+    pub budget_base: u16,
+}
+
+impl Balance {
+    // every difficulty and enemy type needs an entry, a balance with missing ones isn't used
+    pub fn missing(&self) -> Vec<String> {
+        let difficulties = DIFFICULTIES
+            .iter()
+            .filter(|difficulty| !self.difficulties.contains_key(difficulty))
+            .map(|difficulty| format!("difficulty {:?} is missing", difficulty));
+        let enemies = ENEMY_TYPES
+            .iter()
+            .filter(|ty| !self.enemies.contains_key(ty))
+            .map(|ty| format!("enemy {:?} is missing", ty));
+        difficulties.chain(enemies).collect()
+    }
+
+    pub fn difficulty(&self, difficulty: Difficulty) -> DifficultyStats {
+        *self
+            .difficulties
+            .get(&difficulty)
+            .expect("error getting difficulty stats")
+    }
+
+    pub fn enemy(&self, ty: EnemyType) -> EnemyStats {
+        *self.enemies.get(&ty).expect("error getting enemy stats")
+    }
+
+    // the total difficulty of the enemies in a wave, each enemy counts double
+    pub fn wave_budget(&self, wave: u8, difficulty: Difficulty) -> u16 {
+        let budget = usize::from(wave)
+            .checked_sub(1)
+            .and_then(|i| self.wave_budgets.get(i).copied())
+            .unwrap_or(u16::from(wave) * self.budget_per_wave + self.budget_base);
+        ((f32::from(budget) * self.difficulty(difficulty).wave_budget).round() as u16).max(2)
+    }
+}
+
+impl Default for Balance {
+    fn default() -> Self {
+        let difficulties = [
+            (
+                Difficulty::Easy,
+                DifficultyStats {
+                    wave_budget: 0.75,
+                    enemy_reload: 1.35,
+                    bullet_speed: 0.8,
+                    starting_hp: 8,
+                    respawn_hp: 7,
+                    wave_heal: 2,
+                    deflector_reload: 0.75,
+                },
+            ),
+            (
+                Difficulty::Normal,
+                DifficultyStats {
+                    wave_budget: 1.0,
+                    enemy_reload: 1.0,
+                    bullet_speed: 1.0,
+                    starting_hp: 6,
+                    respawn_hp: 5,
+                    wave_heal: 1,
+                    deflector_reload: 1.0,
+                },
+            ),
+            (
+                Difficulty::Hard,
+                DifficultyStats {
+                    wave_budget: 1.25,
+                    enemy_reload: 0.8,
+                    bullet_speed: 1.15,
+                    starting_hp: 5,
+                    respawn_hp: 4,
+                    wave_heal: 1,
+                    deflector_reload: 1.25,
+                },
+            ),
+            (
+                Difficulty::Lunatic,
+                DifficultyStats {
+                    wave_budget: 1.6,
+                    enemy_reload: 0.6,
+                    bullet_speed: 1.3,
+                    starting_hp: 3,
+                    respawn_hp: 3,
+                    wave_heal: 0,
+                    deflector_reload: 1.5,
+                },
+            ),
+        ];
+
+        let enemy = |hp, reload_speed, cost, score| EnemyStats {
+            hp,
+            reload_speed,
+            cost,
+            score,
+        };
+        let enemies = [
+            (EnemyType::BasicEnemy, enemy(3, 180, 1, 100)),
+            (EnemyType::BasicEnemy2, enemy(5, 90, 2, 200)),
+            (EnemyType::AimEnemy, enemy(3, 180, 2, 200)),
+            (EnemyType::AimEnemy2, enemy(5, 90, 4, 400)),
+            (EnemyType::PredictEnemy, enemy(3, 90, 5, 500)),
+            (EnemyType::TrackingEnemy, enemy(3, 180, 5, 500)),
+            (EnemyType::BounceEnemy, enemy(3, 180, 4, 400)),
+        ];
+
+        Balance {
+            difficulties: difficulties.iter().copied().collect(),
+            enemies: enemies.iter().copied().collect(),
+            wave_budgets: vec![12, 14, 20, 24],
+            budget_per_wave: 4,
+            budget_base: 5,
+        }
+    }
+}
+
+// a ship the player can pick before a run, listed in the asset manifest. the one in the world is
+// what the player spawns as
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Ship {
+    pub name: String,
+    // a line for the ship select screen
+    pub description: String,
+    // multiplies how fast the ship moves
+    pub speed: f32,
+    // offset from the sprite's top left, then width and height
+    pub hitbox: (f32, f32, f32, f32),
+    // where bullets leave from, from the sprite's top left
+    pub gun: (f32, f32),
+    // where the engine trail comes from, from the sprite's top left
+    pub thruster: (f32, f32),
+    pub fire_pattern: FirePattern,
+    pub reload_speed: u32,
+    pub deflector: Deflector,
+    // added to the difficulty's starting and respawn hp
+    pub extra_hp: i32,
+    pub sprites: ShipSprites,
+}
+
+// the ship the game had before there were others to pick from
+impl Default for Ship {
+    fn default() -> Self {
+        let player = Player::default();
+        Ship {
+            name: "Striker".to_string(),
+            description: "All-rounder with a single fast shot".to_string(),
+            speed: player.speed,
+            hitbox: (0.0, 0.0, 45.0, 45.0),
+            gun: (player.gun.x, player.gun.y),
+            thruster: (22.5, 45.0),
+            fire_pattern: player.fire_pattern,
+            reload_speed: player.reload_speed,
+            deflector: player.deflector,
+            extra_hp: 0,
+            sprites: ShipSprites::default(),
+        }
+    }
+}
+
+// every ship in the manifest, in the order they're shown
+#[derive(Clone, Debug, Default)]
+pub struct Ships(pub Vec<Ship>);
+
+impl Ships {
+    // falls back to the first ship if there's none called `name`, or to the default one if there
+    // are no ships at all
+    pub fn get(&self, name: &str) -> Ship {
+        self.0
+            .iter()
+            .find(|ship| ship.name == name)
+            .or_else(|| self.0.first())
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+// how the player did on the current wave, rank is adjusted from it when the wave is cleared
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WavePerformance {
+    pub hits: u32,
+    pub deflections: u32,
+    // frames with enemies on screen
+    pub frames: u32,
+    // the total hp of the wave's enemies when it spawned
+    pub enemy_hp: u32,
+}
+
+// how far rank can move the difficulty from its own balance
+pub const RANK_MIN: f32 = 0.8;
+pub const RANK_MAX: f32 = 1.25;
+// a wave is cleared quickly if it takes fewer frames than this per hp of its enemies. the player
+// does 15 damage a second, so this is about half their shots hitting
+const PAR_FRAMES_PER_HP: u32 = 8;
+
+// optional dynamic difficulty. it goes up when waves are cleared quickly without getting hit and
+// down when the player struggles, and scales the wave budget and how often enemies shoot
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rank {
+    pub enabled: bool,
+    pub value: f32,
+    // how much the last cleared wave changed it
+    pub last_change: f32,
+    pub wave: WavePerformance,
+}
+
+impl Default for Rank {
+    fn default() -> Self {
+        Rank::new(false)
+    }
+}
+
+impl Rank {
+    pub fn new(enabled: bool) -> Self {
+        Rank {
+            enabled,
+            value: 1.0,
+            last_change: 0.0,
+            wave: WavePerformance::default(),
+        }
+    }
+
+    pub fn start_wave(&mut self, enemy_hp: u32) {
+        self.wave = WavePerformance {
+            enemy_hp,
+            ..WavePerformance::default()
+        };
+    }
+
+    // called when a wave is cleared, with the hp the player has left
+    pub fn end_wave(&mut self, hp: u32, starting_hp: u32) {
+        if !self.enabled || self.wave.enemy_hp == 0 {
+            return;
+        }
+
+        let wave = self.wave;
+        let mut change = if wave.hits == 0 {
+            0.05
+        } else {
+            -0.04 * wave.hits as f32
+        };
+        // deflecting most of what would have hit is playing well even if some got through
+        if wave.deflections > wave.hits * 2 {
+            change += 0.02;
+        }
+        let par = wave.enemy_hp * PAR_FRAMES_PER_HP;
+        if wave.frames < par {
+            change += 0.03;
+        } else if wave.frames > par * 2 {
+            change -= 0.03;
+        }
+        if hp >= starting_hp {
+            change += 0.02;
+        } else if hp <= 2 {
+            change -= 0.05;
+        }
+
+        let value = (self.value + change).clamp(RANK_MIN, RANK_MAX);
+        self.last_change = value - self.value;
+        self.value = value;
+    }
+
+    pub fn budget(&self, budget: u16) -> u16 {
+        ((f32::from(budget) * self.value).round() as u16).max(2)
+    }
+
+    // enemy reload times are divided by this
+    pub fn fire_rate(&self) -> f32 {
+        self.value
+    }
+}
+
+// gameplay randomness goes through this so that a run can be repeated from its seed.
+// effects that don't change the game, like particles and screen shake, use thread_rng
+pub struct GameRng(pub StdRng);
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng(StdRng::from_entropy())
+    }
+}
+
+impl GameRng {
+    pub fn seeded(seed: u64) -> Self {
+        GameRng(StdRng::seed_from_u64(seed))
+    }
+}
+
+// what hit the player
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DamageSource {
+    Bullet(BulletType),
+    Enemy(EnemyType),
+}
+
+// what happened during the last frame, for tools that watch the game like the bot.
+// cleared at the start of every frame
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameEvents {
+    pub player_hits: Vec<DamageSource>,
+    pub deflections: u32,
+    pub grazes: u32,
+}
+
+// the player can't take damage, toggled from the debug console
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct GodMode(pub bool);
+
+// how long each system took over the last few frames, filled in by systems::Timed. the mutex
+// lets every system record its time without making them all depend on each other
+#[derive(Default)]
+pub struct SystemTimings(pub Mutex<Profiler>);
+
+impl SystemTimings {
+    pub fn record(&self, name: &'static str, start: Instant, duration: Duration) {
+        self.0
+            .lock()
+            .expect("error locking system timings")
+            .record(name, start, duration);
+    }
+
+    pub fn end_frame(&self) {
+        self.0
+            .lock()
+            .expect("error locking system timings")
+            .end_frame();
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct FramesToNextWave(pub u16);
 impl Default for FramesToNextWave {
@@ -55,32 +522,93 @@ impl Default for FramesToNextWave {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Default)]
-pub struct StarInfo {
+// a single parallax layer of stars, closer layers should be bigger and faster
+#[derive(Clone, Debug, PartialEq)]
+pub struct StarLayer {
     pub num_stars: usize,
     pub size: f32,
     pub size_variance: f32,
     pub vel: f32,
     pub vel_variance: f32,
+    pub color: Color,
+    // how much the alpha flickers, 0.0 is no twinkling
+    pub twinkle: f32,
+    // optional sprite key for a background image that scrolls with the layer
+    pub image: Option<String>,
+}
+
+// stages switch on wave milestones and cross-fade into each other
+#[derive(Clone, Debug, PartialEq)]
+pub struct StarStage {
+    pub start_wave: u8,
+    pub layers: Vec<StarLayer>,
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct StarInfo {
+    pub stages: Vec<StarStage>,
+    pub current_stage: usize,
+    pub previous_stage: usize,
+    // goes from 0.0 to 1.0 while the current stage fades in
+    pub fade: f32,
+    // vertical offset of each background image, indexed by stage and then layer
+    pub scroll: Vec<Vec<f32>>,
 }
 
 impl StarInfo {
-    pub fn new_star(&self) -> (Position, Velocity, ColorRect) {
+    pub fn new(stages: Vec<StarStage>) -> Self {
+        StarInfo {
+            stages,
+            current_stage: 0,
+            previous_stage: 0,
+            fade: 1.0,
+            scroll: Vec::new(),
+        }
+    }
+
+    pub fn layers(&self, stage: usize) -> &[StarLayer] {
+        self.stages
+            .get(stage)
+            .map(|stage| stage.layers.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn scroll(&self, stage: usize) -> &[f32] {
+        self.scroll
+            .get(stage)
+            .map(|scroll| scroll.as_slice())
+            .unwrap_or(&[])
+    }
+
+    // the last stage which has started by the given wave
+    pub fn stage_for_wave(&self, wave: u8) -> usize {
+        self.stages
+            .iter()
+            .rposition(|stage| stage.start_wave <= wave)
+            .unwrap_or(0)
+    }
+
+    pub fn new_star(&self, stage: usize, layer: usize) -> (Position, Velocity, ColorRect) {
         use rand::Rng;
 
+        let layer = &self.layers(stage)[layer];
+
         let mut rng = rand::thread_rng();
         let x = rng.gen_range(0.0, crate::SCREEN_WIDTH);
         let y = rng.gen_range(-crate::SCREEN_WIDTH, 0.0);
-        let y_vel = rng.gen_range(self.vel - self.vel_variance, self.vel + self.vel_variance);
+        let y_vel = rng.gen_range(
+            layer.vel - layer.vel_variance,
+            layer.vel + layer.vel_variance,
+        );
         let size = rng.gen_range(
-            self.size - self.size_variance,
-            self.size + self.size_variance,
+            layer.size - layer.size_variance,
+            layer.size + layer.size_variance,
         );
 
         let pos = [x, y].into();
         let vel = [0.0, y_vel].into();
         let color_rect = ColorRect {
-            color: ggez::graphics::Color::new(1.0, 1.0, 1.0, 0.35),
+            color: layer.color,
             w: size,
             h: size,
         };
@@ -89,11 +617,88 @@ impl StarInfo {
     }
 }
 
+// particle effects queued by systems, spawned by the ParticleSys
+#[derive(Clone, Default)]
+pub struct QueuedParticles(pub Vec<(Point, ParticleEffect)>);
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct ParticleInfo {
+    pub max_particles: usize,
+}
+
 #[derive(Clone, Default)]
 pub struct Sounds(pub HashMap<String, SoundData>);
 
 #[derive(Clone, Default)]
-pub struct QueuedSounds(pub Vec<SoundData>);
+pub struct Music(pub HashMap<String, SoundData>);
+
+#[derive(Clone, Default)]
+pub struct SoundConfigs(pub HashMap<String, SoundConfig>);
+
+impl SoundConfigs {
+    pub fn get(&self, key: &str) -> SoundConfig {
+        self.0.get(key).copied().unwrap_or_default()
+    }
+}
+
+// the music track that should be playing, set by the music director
+#[derive(Clone, Default)]
+pub struct DesiredMusic(pub Option<String>);
+
+// a sound waiting to be played. sounds with a position are panned left or right by their x,
+// and when there are too many sounds playing at once the low priority ones are dropped
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QueuedSound {
+    pub key: &'static str,
+    pub pos: Option<Point>,
+    pub priority: u8,
+}
+
+impl QueuedSound {
+    pub fn at(key: &'static str, pos: Point, priority: u8) -> Self {
+        QueuedSound {
+            key,
+            pos: Some(pos),
+            priority,
+        }
+    }
+}
+
+// sounds are queued by key and played by the mixer
+#[derive(Clone, Default)]
+pub struct QueuedSounds(pub Vec<QueuedSound>);
 
 #[derive(Clone, Default)]
 pub struct LastUpdate(pub Duration);
+
+// trauma is added by hits and decays every frame,
+// the screen shakes proportionally to trauma squared
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub trauma: f32,
+    pub offset: Vector,
+    pub hit_stop_frames: u8,
+    pub shake_enabled: bool,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            trauma: 0.0,
+            offset: Vector::new(0.0, 0.0),
+            hit_stop_frames: 0,
+            shake_enabled: true,
+        }
+    }
+}
+
+impl Camera {
+    pub fn add_trauma(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).min(1.0);
+    }
+
+    // freezes the game for a few frames
+    pub fn hit_stop(&mut self, frames: u8) {
+        self.hit_stop_frames = self.hit_stop_frames.max(frames);
+    }
+}
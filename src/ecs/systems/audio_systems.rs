@@ -0,0 +1,22 @@
+use crate::audio::MusicTracks;
+use crate::ecs::resources::*;
+use specs::prelude::*;
+
+// picks the music track for the current state of the game,
+// the mixer cross-fades to it when it changes
+pub struct MusicDirectorSys;
+impl<'a> System<'a> for MusicDirectorSys {
+    type SystemData = (
+        Read<'a, MusicTracks>,
+        Read<'a, CurrentWave>,
+        Read<'a, Dead>,
+        Write<'a, DesiredMusic>,
+    );
+
+    fn run(&mut self, (music_tracks, current_wave, dead, mut desired_music): Self::SystemData) {
+        let track = music_tracks.track(current_wave.0, dead.0);
+        if desired_music.0.as_deref() != track {
+            desired_music.0 = track.map(str::to_string);
+        }
+    }
+}
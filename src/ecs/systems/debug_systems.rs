@@ -0,0 +1,42 @@
+use crate::ecs::resources::*;
+use specs::prelude::*;
+
+use std::time::Instant;
+
+// wraps a system and records when it ran and for how long in SystemTimings
+pub struct Timed<S> {
+    name: &'static str,
+    system: S,
+}
+
+impl<'a, S> System<'a> for Timed<S>
+where
+    S: System<'a>,
+    S::SystemData: SystemData<'a>,
+{
+    type SystemData = (S::SystemData, Read<'a, SystemTimings>);
+
+    fn run(&mut self, (data, timings): Self::SystemData) {
+        let start = Instant::now();
+        self.system.run(data);
+        timings.record(self.name, start, start.elapsed());
+    }
+}
+
+pub trait WithTimed<'a> {
+    // the same as `with`, but the system is timed under its name
+    fn with_timed<S>(self, system: S, name: &'static str, dep: &[&str]) -> Self
+    where
+        S: for<'c> System<'c> + Send + 'a,
+        for<'c> <S as System<'c>>::SystemData: SystemData<'c>;
+}
+
+impl<'a, 'b> WithTimed<'a> for DispatcherBuilder<'a, 'b> {
+    fn with_timed<S>(self, system: S, name: &'static str, dep: &[&str]) -> Self
+    where
+        S: for<'c> System<'c> + Send + 'a,
+        for<'c> <S as System<'c>>::SystemData: SystemData<'c>,
+    {
+        self.with(Timed { name, system }, name, dep)
+    }
+}
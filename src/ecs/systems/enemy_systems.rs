@@ -53,6 +53,9 @@ impl<'a> System<'a> for EnemyShootSys {
         Read<'a, PlayerEntity>,
         Read<'a, Dead>,
         Read<'a, LazyUpdate>,
+        Write<'a, GameRng>,
+        Read<'a, Difficulty>,
+        Read<'a, Balance>,
     );
 
     fn run(
@@ -66,6 +69,9 @@ impl<'a> System<'a> for EnemyShootSys {
             player_entity,
             dead,
             lazy_update,
+            mut rng,
+            difficulty,
+            balance,
         ): Self::SystemData,
     ) {
         if dead.0 {
@@ -94,20 +100,21 @@ impl<'a> System<'a> for EnemyShootSys {
             .expect("error getting player velocity")
             .0;
 
+        let speed_scale = balance.difficulty(*difficulty).bullet_speed;
         new_bullets.iter().for_each(|(pos, bullet_type)| {
             let vel = match bullet_type {
                 BulletType::PlayerBullet => unreachable!(),
-                BulletType::BasicBullet => [0.0, 8.0].into(),
+                BulletType::BasicBullet => [0.0, 8.0 * speed_scale].into(),
                 BulletType::AimedBullet | BulletType::TrackingBullet(_) => {
                     let speed = match bullet_type {
                         BulletType::AimedBullet => 9.0,
                         BulletType::TrackingBullet(_) => 5.0,
                         _ => unreachable!(),
                     };
-                    (player_pos - pos).normalize() * speed
+                    (player_pos - pos).normalize() * speed * speed_scale
                 }
                 BulletType::PredictBullet => {
-                    let bullet_speed = 10.0f32;
+                    let bullet_speed = 10.0 * speed_scale;
 
                     let mut player_projected_pos = player_pos;
 
@@ -126,9 +133,8 @@ impl<'a> System<'a> for EnemyShootSys {
                     direction * bullet_speed
                 }
                 BulletType::BouncingBullet(num_bounces) => {
-                    use rand::prelude::*;;
-                    let mut rng = rand::thread_rng();
-                    let direction_is_right = rng.gen_bool(0.5); // false is left
+                    use rand::Rng;
+                    let direction_is_right = rng.0.gen_bool(0.5); // false is left
 
                     //  |         O  |     |       O
                     //  |          \ |     |        \
@@ -172,7 +178,7 @@ impl<'a> System<'a> for EnemyShootSys {
                         direction.x *= -1.0;
                     }
 
-                    let speed = 8.0;
+                    let speed = 8.0 * speed_scale;
                     [speed * direction.x, speed * direction.y].into()
                 }
             };
@@ -185,18 +191,21 @@ impl<'a> System<'a> for EnemyShootSys {
             let spritesheet = spritesheets
                 .0
                 .get("bullets")
-                .expect("error getting bullet spritesheet")
-                .clone();
+                .expect("error getting bullet spritesheet");
+            let (sprite, hitbox) = spritesheet_frame(spritesheet, bullet_tuple.3);
 
             let bullet = entities.create();
             lazy_update.insert(bullet, bullet_tuple.0);
+            lazy_update.insert(bullet, hitbox);
             lazy_update.insert(bullet, bullet_tuple.1);
             lazy_update.insert(bullet, bullet_tuple.2);
-            lazy_update.insert(bullet, bullet_tuple.3);
-            lazy_update.insert(
-                bullet,
-                Sprite::SpriteSheetInstance(spritesheet, bullet_tuple.4),
-            );
+            lazy_update.insert(bullet, sprite);
+            if let BulletType::TrackingBullet(_) = bullet_type {
+                lazy_update.insert(
+                    bullet,
+                    ParticleEmitter::new(ParticleEffect::Trail, Vector::new(12.5, 12.5), 1),
+                );
+            }
         });
     }
 }
@@ -204,9 +213,18 @@ impl<'a> System<'a> for EnemyShootSys {
 #[derive(Default)]
 pub struct WaveCalcSys;
 impl<'a> System<'a> for WaveCalcSys {
-    type SystemData = (Write<'a, QueuedEnemies>, Read<'a, CurrentWave>);
+    type SystemData = (
+        Write<'a, QueuedEnemies>,
+        Read<'a, CurrentWave>,
+        Read<'a, Difficulty>,
+        Read<'a, Rank>,
+        Read<'a, Balance>,
+    );
 
-    fn run(&mut self, (mut queued_enemies, current_wave): Self::SystemData) {
+    fn run(
+        &mut self,
+        (mut queued_enemies, current_wave, difficulty, rank, balance): Self::SystemData,
+    ) {
         use std::collections::HashMap;
 
         let enemies = &mut queued_enemies.0;
@@ -214,52 +232,26 @@ impl<'a> System<'a> for WaveCalcSys {
 
         let mut new_enemies = Vec::new();
         let mut counter: HashMap<EnemyType, u8> = HashMap::new();
-        let target_difficulty = match current_wave.0 {
-            1 => 12,
-            2 => 14,
-            3 => 20,
-            4 => 24,
-            _ => current_wave.0 as u16 * 4 + 5,
-        };
+        let target_difficulty = rank.budget(balance.wave_budget(current_wave.0, *difficulty));
         let mut difficulty = 0u16;
 
-        fn calc_diff(ty: EnemyType) -> u16 {
-            match ty {
-                EnemyType::BasicEnemy => 1,
-                EnemyType::BasicEnemy2 => 2,
-                EnemyType::AimEnemy => 2,
-                EnemyType::AimEnemy2 => 4,
-                EnemyType::BounceEnemy => 4,
-                EnemyType::PredictEnemy => 5,
-                EnemyType::TrackingEnemy => 5,
-            }
-        }
-
         while difficulty < target_difficulty {
-            let new_enemy = [
-                EnemyType::BasicEnemy,
-                EnemyType::BasicEnemy2,
-                EnemyType::AimEnemy,
-                EnemyType::AimEnemy2,
-                EnemyType::PredictEnemy,
-                EnemyType::TrackingEnemy,
-                EnemyType::BounceEnemy,
-            ]
-            .iter()
-            .filter_map(|enemy_ty| {
-                let diff = calc_diff(*enemy_ty);
-                if diff < (target_difficulty - difficulty)
-                    && (diff as f32) < target_difficulty as f32 / 4.0
-                {
-                    Some((enemy_ty, diff))
-                } else {
-                    None
-                }
-            })
-            .max_by_key(|(ty, diff)| {
-                *diff - (((*counter.get(ty).unwrap_or(&0) as u16).pow(2)) * 3).min(*diff)
-            })
-            .unwrap_or((&EnemyType::BasicEnemy, 1));
+            let new_enemy = ENEMY_TYPES
+                .iter()
+                .filter_map(|enemy_ty| {
+                    let diff = balance.enemy(*enemy_ty).cost;
+                    if diff < (target_difficulty - difficulty)
+                        && (diff as f32) < target_difficulty as f32 / 4.0
+                    {
+                        Some((enemy_ty, diff))
+                    } else {
+                        None
+                    }
+                })
+                .max_by_key(|(ty, diff)| {
+                    *diff - (((*counter.get(ty).unwrap_or(&0) as u16).pow(2)) * 3).min(*diff)
+                })
+                .unwrap_or((&EnemyType::BasicEnemy, 1));
             difficulty += new_enemy.1 * 2;
             if let Some(count) = counter.get_mut(new_enemy.0) {
                 *count += 1;
@@ -283,3 +275,25 @@ impl<'a> System<'a> for WaveCalcSys {
         enemies.append(&mut new_enemies);
     }
 }
+
+// keeps track of how the player is doing on the current wave for rank, see Rank::end_wave
+pub struct RankSys;
+impl<'a> System<'a> for RankSys {
+    type SystemData = (
+        Write<'a, Rank>,
+        Read<'a, FrameEvents>,
+        ReadStorage<'a, Enemy>,
+    );
+
+    fn run(&mut self, (mut rank, frame_events, enemies): Self::SystemData) {
+        if !rank.enabled {
+            return;
+        }
+
+        rank.wave.hits += frame_events.player_hits.len() as u32;
+        rank.wave.deflections += frame_events.deflections;
+        if enemies.join().next().is_some() {
+            rank.wave.frames += 1;
+        }
+    }
+}
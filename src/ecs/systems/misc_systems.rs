@@ -29,6 +29,12 @@ impl<'a> System<'a> for IFrameSys {
     }
 }
 
+// a bullet that comes this close to the player's hitbox without touching it is grazed, which is
+// worth some score and takes some frames off the deflector's cooldown
+const GRAZE_DISTANCE: f32 = 14.0;
+const GRAZE_SCORE: u32 = 50;
+const GRAZE_CHARGE_FRAMES: u32 = 8;
+
 pub struct BulletCollSys;
 impl<'a> System<'a> for BulletCollSys {
     type SystemData = (
@@ -39,13 +45,20 @@ impl<'a> System<'a> for BulletCollSys {
         WriteStorage<'a, Player>,
         WriteStorage<'a, Velocity>,
         ReadStorage<'a, Deflected>,
+        ReadStorage<'a, Grazing>,
+        ReadStorage<'a, Grazed>,
+        ReadStorage<'a, Enemy>,
         Entities<'a>,
         Read<'a, AnimatedSprites>,
         Read<'a, PlayerEntity>,
-        Write<'a, HPText>,
-        Read<'a, Sounds>,
         Write<'a, QueuedSounds>,
+        Write<'a, QueuedParticles>,
+        Write<'a, Camera>,
         Read<'a, LazyUpdate>,
+        Read<'a, GodMode>,
+        Write<'a, FrameEvents>,
+        Write<'a, Score>,
+        Write<'a, GrazeCount>,
     );
 
     fn run(
@@ -58,18 +71,49 @@ impl<'a> System<'a> for BulletCollSys {
             mut players,
             mut vels,
             deflecteds,
+            grazings,
+            grazeds,
+            enemies,
             entities,
             animated_sprites,
             player_entity,
-            mut hp_text,
-            sounds,
             mut queued_sounds,
+            mut queued_particles,
+            mut camera,
             lazy_update,
+            god_mode,
+            mut frame_events,
+            mut score,
+            mut graze_count,
         ): Self::SystemData,
     ) {
-        let mut atleast_one_explosion = false;
-        let mut atleast_one_deflection = false;
         let mut player_deflection_hp = 0;
+        // where the bullets that got away this frame were, they're counted after the loop
+        let mut grazes = Vec::new();
+
+        let player_rect = match (
+            positions.get(player_entity.0),
+            hitboxes.get(player_entity.0),
+        ) {
+            (Some(pos), Some(hitbox)) => Some(Rect::new(
+                pos.0.x + hitbox.0.x,
+                pos.0.y + hitbox.0.y,
+                hitbox.1,
+                hitbox.2,
+            )),
+            _ => None,
+        };
+
+        // deflected bullets are aimed at the closest of these
+        let enemy_centers: Vec<Point> = (&enemies, &positions, &hitboxes)
+            .join()
+            .map(|(_, pos, hitbox)| {
+                Point::new(
+                    pos.0.x + hitbox.0.x + hitbox.1 / 2.0,
+                    pos.0.y + hitbox.0.y + hitbox.2 / 2.0,
+                )
+            })
+            .collect();
 
         let sprite = animated_sprites
             .0
@@ -84,17 +128,116 @@ impl<'a> System<'a> for BulletCollSys {
                     bullet_hitbox.1,
                     bullet_hitbox.2,
                 );
+                let bullet_center = Point::new(
+                    bullet_rect.x + bullet_rect.w / 2.0,
+                    bullet_rect.y + bullet_rect.h / 2.0,
+                );
                 if !(-10.0..crate::SCREEN_WIDTH + 10.0).contains(&pos.0.x)
                     || !(-10.0..crate::SCREEN_HEIGHT).contains(&pos.0.y)
                 {
                     if entities.delete(bullet_entity).is_err() {
                         log::warn!("error deleting offscreen bullet entity")
                     }
+                    if grazings.get(bullet_entity).is_some() {
+                        grazes.push(bullet_center);
+                    }
                 } else {
+                    // the deflector sends bullets back before they can be grazed or hit
+                    let deflector = match (players.get_mut(player_entity.0), player_rect) {
+                        (Some(player), Some(player_rect)) if player.deflector_timer > 0 => {
+                            Some((player, player_rect))
+                        }
+                        _ => None,
+                    };
+                    if let (true, Some((player, player_rect))) =
+                        (bullet.damages_player(), deflector)
+                    {
+                        let radius = player.deflector_radius(player.deflector_level);
+                        let deflect_rect = Rect::new(
+                            player_rect.x - radius,
+                            player_rect.y - radius,
+                            player_rect.w + radius * 2.0,
+                            player_rect.h + radius * 2.0,
+                        );
+                        if bullet_rect.overlaps(&deflect_rect) {
+                            let player_center = Point::new(
+                                player_rect.x + player_rect.w / 2.0,
+                                player_rect.y + player_rect.h / 2.0,
+                            );
+                            let player_vel = vels
+                                .get(player_entity.0)
+                                .expect("error getting player vel")
+                                .0;
+                            let target = enemy_centers.iter().min_by(|a, b| {
+                                (*a - bullet_center)
+                                    .norm()
+                                    .total_cmp(&(*b - bullet_center).norm())
+                            });
+                            let bullet_vel = &mut vels
+                                .get_mut(bullet_entity)
+                                .expect("error getting deflected bullet vel")
+                                .0;
+                            *bullet_vel = deflected_vel(
+                                *bullet_vel,
+                                (bullet_center - player_center).normalize(),
+                                player_vel,
+                                target.map(|target| target - bullet_center),
+                            );
+
+                            let heal =
+                                if let BulletType::TrackingBullet(frames_remaining) = bullet.ty {
+                                    if frames_remaining > 10 {
+                                        bullet.ty = BulletType::TrackingBullet(10);
+                                    }
+                                    0
+                                } else {
+                                    bullet.damages_who = DamagesWho::Enemy;
+                                    let heal = bullet.damage * 2;
+                                    bullet.damage *= 2 + player.deflector_level;
+                                    heal
+                                };
+
+                            lazy_update.insert(bullet_entity, Deflected(heal));
+                            lazy_update.remove::<Grazing>(bullet_entity);
+                            frame_events.deflections += 1;
+                            queued_particles
+                                .0
+                                .push((bullet_center, ParticleEffect::Spark));
+
+                            player.deflector_timer = player.deflector.active_frames * 2;
+                            camera.hit_stop(4);
+                            queued_sounds.0.push(QueuedSound::at("deflect", pos.0, 2));
+                            return;
+                        }
+                    }
+
+                    let grazeable = bullet.damages_player()
+                        && !god_mode.0
+                        && grazeds.get(bullet_entity).is_none();
+                    if let (true, Some(player_rect)) = (grazeable, player_rect) {
+                        let graze_rect = Rect::new(
+                            player_rect.x - GRAZE_DISTANCE,
+                            player_rect.y - GRAZE_DISTANCE,
+                            player_rect.w + GRAZE_DISTANCE * 2.0,
+                            player_rect.h + GRAZE_DISTANCE * 2.0,
+                        );
+                        // only counted once the bullet is out of the band without having hit,
+                        // a bullet that flies straight into the player isn't a graze
+                        let grazing = grazings.get(bullet_entity).is_some();
+                        let in_band = bullet_rect.overlaps(&graze_rect);
+                        if in_band && !grazing && !bullet_rect.overlaps(&player_rect) {
+                            lazy_update.insert(bullet_entity, Grazing);
+                        } else if !in_band && grazing {
+                            lazy_update.remove::<Grazing>(bullet_entity);
+                            lazy_update.insert(bullet_entity, Grazed);
+                            grazes.push(bullet_center);
+                        }
+                    }
+
                     (&mut hp_storage, &positions, &hitboxes, &entities)
                         .join()
                         .for_each(|(hp, collided_pos, hitbox, entity)| {
-                            if (bullet.damages_player() && entity == player_entity.0)
+                            if (bullet.damages_player() && entity == player_entity.0 && !god_mode.0)
                                 || (bullet.damages_enemy() && entity != player_entity.0)
                                     && hp.remaining > 0
                             {
@@ -106,67 +249,10 @@ impl<'a> System<'a> for BulletCollSys {
                                 );
                                 if bullet_rect.overlaps(&collidee_rect) {
                                     if entity == player_entity.0 {
-                                        hp_text.needs_redraw = true;
-                                        let player = players
-                                            .get_mut(player_entity.0)
-                                            .expect("error getting player entity");
-
-                                        let player_vel = vels
-                                            .get(player_entity.0)
-                                            .expect("error getting player vel")
-                                            .0;
-
-                                        if player.deflector_timer > 0 {
-                                            let bullet_vel = &mut vels
-                                                .get_mut(bullet_entity)
-                                                .expect("error getting deflected bullet vel")
-                                                .0;
-
-                                            let normal = {
-                                                let bullet_center = Point::new(
-                                                    bullet_rect.x + bullet_rect.w / 2.0,
-                                                    bullet_rect.y + bullet_rect.h / 2.0,
-                                                );
-                                                let player_center = Point::new(
-                                                    collidee_rect.x + collidee_rect.w / 2.0,
-                                                    collidee_rect.y + collidee_rect.h / 2.0,
-                                                );
-
-                                                (bullet_center - player_center).normalize()
-                                            };
-
-                                            let bullet_vel_dot_normal =
-                                                bullet_vel.x * normal.x + bullet_vel.y * normal.y;
-
-                                            // dbg!(bullet_vel, 2.0 * bullet_vel_dot_normal * normal);
-                                            *bullet_vel -= 2.0 * bullet_vel_dot_normal * normal;
-                                            *bullet_vel += player_vel;
-                                            if bullet_vel.x.abs() < 8.0 {
-                                                bullet_vel.x = 0.0;
-                                            }
-
-                                            let bullet_speed = bullet_vel.norm();
-                                            if bullet_speed < 8.0 {
-                                                *bullet_vel *= 8.0 / bullet_speed;
-                                            }
-
-                                            if let BulletType::TrackingBullet(frames_remaining) =
-                                                bullet.ty
-                                            {
-                                                if frames_remaining > 10 {
-                                                    bullet.ty = BulletType::TrackingBullet(10);
-                                                }
-                                            } else {
-                                                bullet.damages_who = DamagesWho::Enemy;
-                                                bullet.damage *= 3;
-                                            }
-
-                                            lazy_update.insert(bullet_entity, Deflected::default());
-
-                                            player.deflector_timer = player.deflector_frames * 2;
-                                            atleast_one_deflection = true;
-                                            return;
-                                        }
+                                        camera.add_trauma(0.35);
+                                        frame_events
+                                            .player_hits
+                                            .push(DamageSource::Bullet(bullet.ty));
                                     }
 
                                     if entities.delete(bullet_entity).is_err() {
@@ -175,11 +261,13 @@ impl<'a> System<'a> for BulletCollSys {
                                     let explosion = entities.create();
                                     lazy_update.insert(explosion, *pos);
                                     lazy_update.insert(explosion, sprite.clone());
-                                    atleast_one_explosion = true;
+                                    queued_particles
+                                        .0
+                                        .push((bullet_center, ParticleEffect::Spark));
+                                    queued_sounds.0.push(QueuedSound::at("boom", pos.0, 1));
 
-                                    if deflecteds.get(bullet_entity).is_some() {
-                                        player_deflection_hp += bullet.damage / 3 * 2;
-                                        // deflected bullets do triple damage, and we want them to heal double damage
+                                    if let Some(deflected) = deflecteds.get(bullet_entity) {
+                                        player_deflection_hp += deflected.0;
                                     }
                                     hp.remaining -= bullet.damage.min(hp.remaining);
                                 }
@@ -188,25 +276,63 @@ impl<'a> System<'a> for BulletCollSys {
                 }
             });
 
-        if atleast_one_explosion {
-            if let Some(sound) = sounds.0.get("boom") {
-                queued_sounds.0.push(sound.clone());
-            } else {
-                log::warn!("error playing explosion sound");
-            }
-        }
-        if atleast_one_deflection {
-            if let Some(sound) = sounds.0.get("deflect") {
-                queued_sounds.0.push(sound.clone());
-            } else {
-                log::warn!("error playing deflection sound");
-            }
-        }
-
         if player_deflection_hp > 0 {
             hp_storage.get_mut(player_entity.0).unwrap().remaining += player_deflection_hp;
-            hp_text.needs_redraw = true;
         }
+
+        grazes.into_iter().for_each(|bullet_center| {
+            graze_count.0 += 1;
+            frame_events.grazes += 1;
+            score.add(GRAZE_SCORE);
+            if let Some(player) = players.get_mut(player_entity.0) {
+                player.deflector_cooldown = player
+                    .deflector_cooldown
+                    .saturating_sub(GRAZE_CHARGE_FRAMES);
+            }
+            queued_particles
+                .0
+                .push((bullet_center, ParticleEffect::Graze));
+        });
+    }
+}
+
+// bounces a bullet off the deflector along `normal`, or sends it towards `aim` if there's an
+// enemy to aim at. either way it goes back at least as fast as it bounces
+fn deflected_vel(vel: Vector, normal: Vector, player_vel: Vector, aim: Option<Vector>) -> Vector {
+    let mut vel = vel - 2.0 * vel.dot(&normal) * normal + player_vel;
+    if vel.x.abs() < 8.0 {
+        vel.x = 0.0;
+    }
+
+    let speed = vel.norm();
+    if speed < 8.0 {
+        vel *= 8.0 / speed;
+    }
+
+    match aim {
+        Some(aim) if aim.norm() > 0.0 => aim.normalize() * vel.norm(),
+        _ => vel,
+    }
+}
+
+const MAX_SHAKE_OFFSET: f32 = 14.0;
+
+pub struct CameraSys;
+impl<'a> System<'a> for CameraSys {
+    type SystemData = Write<'a, Camera>;
+
+    fn run(&mut self, mut camera: Self::SystemData) {
+        use rand::Rng;
+
+        let shake = if camera.shake_enabled {
+            MAX_SHAKE_OFFSET * camera.trauma.powi(2)
+        } else {
+            0.0
+        };
+
+        let mut rng = rand::thread_rng();
+        camera.offset = Vector::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0)) * shake;
+        camera.trauma = (camera.trauma - 0.02).max(0.0);
     }
 }
 
@@ -240,10 +366,11 @@ impl<'a> System<'a> for BulletTrackingSys {
         WriteStorage<'a, Bullet>,
         Read<'a, PlayerEntity>,
         Read<'a, AnimatedSprites>,
-        Read<'a, Sounds>,
         Write<'a, QueuedSounds>,
         Entities<'a>,
         Read<'a, LazyUpdate>,
+        Read<'a, Difficulty>,
+        Read<'a, Balance>,
     );
 
     fn run(
@@ -254,15 +381,15 @@ impl<'a> System<'a> for BulletTrackingSys {
             mut bullets,
             player_entity,
             animated_sprites,
-            sounds,
             mut queued_sounds,
             entities,
             lazy_update,
+            difficulty,
+            balance,
         ): Self::SystemData,
     ) {
         if let Some(player_pos) = positions.get(player_entity.0) {
             let player_pos = player_pos.0;
-            let mut atleast_one_explosion = std::sync::atomic::AtomicBool::new(false);
             let explosion_sprite = animated_sprites
                 .0
                 .get("explosion")
@@ -280,26 +407,19 @@ impl<'a> System<'a> for BulletTrackingSys {
                             entities
                                 .delete(entity)
                                 .expect("error deleting dead tracking bullet");
-                            *atleast_one_explosion.get_mut() = true;
+                            queued_sounds.0.push(QueuedSound::at("boom", pos.0, 1));
                             return;
                         }
                         new_ty = Some(BulletType::TrackingBullet(frames_remaining - 1));
                         let direction = (player_pos - pos.0).normalize();
-                        let target_vel = direction * 7.0;
+                        let target_vel =
+                            direction * 7.0 * balance.difficulty(*difficulty).bullet_speed;
                         vel.0 += (target_vel - vel.0) * 0.02;
                     }
                     if let Some(ty) = new_ty {
                         bullet.ty = ty;
                     }
                 });
-
-            if atleast_one_explosion.into_inner() {
-                if let Some(explosion_sound) = sounds.0.get("boom") {
-                    queued_sounds.0.push(explosion_sound.clone());
-                } else {
-                    log::warn!("error getting explosion sound");
-                }
-            }
         }
     }
 }
@@ -308,27 +428,61 @@ pub struct HPKillSys;
 impl<'a> System<'a> for HPKillSys {
     type SystemData = (
         ReadStorage<'a, HP>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Hitbox>,
         Entities<'a>,
         Read<'a, PlayerEntity>,
         Write<'a, Dead>,
-        Read<'a, Sounds>,
         Write<'a, QueuedSounds>,
+        Write<'a, QueuedParticles>,
+        Write<'a, Camera>,
+        ReadStorage<'a, Enemy>,
+        Write<'a, Score>,
+        Read<'a, Balance>,
     );
 
     fn run(
         &mut self,
-        (hp_storage, entities, player_entity, mut dead, sounds, mut queued_sounds): Self::SystemData,
+        (
+            hp_storage,
+            positions,
+            hitboxes,
+            entities,
+            player_entity,
+            mut dead,
+            mut queued_sounds,
+            mut queued_particles,
+            mut camera,
+            enemies,
+            mut score,
+            balance,
+        ): Self::SystemData,
     ) {
         (&hp_storage, &entities).join().for_each(|(hp, entity)| {
             if hp.remaining == 0 {
                 entities.delete(entity).expect("error deleting dead entity");
+                // debris comes from the middle of the hitbox, for anything that has one
+                let center = match (positions.get(entity), hitboxes.get(entity)) {
+                    (Some(pos), Some(hitbox)) => {
+                        Some(pos.0 + hitbox.0.coords + Vector::new(hitbox.1, hitbox.2) / 2.0)
+                    }
+                    (Some(pos), None) => Some(pos.0),
+                    _ => None,
+                };
+                if let Some(center) = center {
+                    queued_particles.0.push((center, ParticleEffect::Debris));
+                }
                 if entity == player_entity.0 {
                     dead.0 = true;
-                    if let Some(sound) = sounds.0.get("dead") {
-                        queued_sounds.0.push(sound.clone());
-                    } else {
-                        log::warn!("error getting death sound");
-                    }
+                    camera.add_trauma(0.8);
+                    queued_sounds.0.push(QueuedSound {
+                        key: "dead",
+                        pos: center,
+                        priority: 3,
+                    });
+                }
+                if let Some(enemy) = enemies.get(entity) {
+                    score.add(balance.enemy(enemy.ty).score);
                 }
             }
         });
@@ -1,8 +1,16 @@
+mod audio_systems;
+mod debug_systems;
 mod enemy_systems;
 mod misc_systems;
+mod particle_systems;
 mod player_systems;
 mod star_systems;
+pub use audio_systems::*;
+pub use debug_systems::*;
 pub use enemy_systems::*;
 pub use misc_systems::*;
+pub use particle_systems::*;
 pub use player_systems::*;
 pub use star_systems::*;
+#[cfg(test)]
+mod tests;
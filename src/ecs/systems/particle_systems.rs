@@ -0,0 +1,77 @@
+#![allow(clippy::type_complexity)]
+use crate::ecs::components::*;
+use crate::ecs::resources::*;
+use specs::prelude::*;
+
+pub struct ParticleEmitterSys;
+impl<'a> System<'a> for ParticleEmitterSys {
+    type SystemData = (
+        ReadStorage<'a, Position>,
+        WriteStorage<'a, ParticleEmitter>,
+        Write<'a, QueuedParticles>,
+    );
+
+    fn run(&mut self, (positions, mut emitters, mut queued_particles): Self::SystemData) {
+        (&positions, &mut emitters)
+            .join()
+            .for_each(|(pos, emitter)| {
+                if emitter.timer == 0 {
+                    emitter.timer = emitter.interval;
+                    queued_particles
+                        .0
+                        .push((pos.0 + emitter.offset, emitter.effect));
+                } else {
+                    emitter.timer -= 1;
+                }
+            });
+    }
+}
+
+pub struct ParticleSys;
+impl<'a> System<'a> for ParticleSys {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Particle>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Velocity>,
+        Write<'a, QueuedParticles>,
+        Read<'a, ParticleInfo>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, mut particles, mut positions, mut vels, mut queued_particles, particle_info): Self::SystemData,
+    ) {
+        let mut num_particles = 0;
+        (&mut particles, &mut vels, &entities)
+            .join()
+            .for_each(|(particle, vel, entity)| {
+                particle.age += 1;
+                vel.0 *= particle.drag;
+                if particle.age >= particle.lifetime {
+                    if entities.delete(entity).is_err() {
+                        log::warn!("error deleting expired particle");
+                    }
+                } else {
+                    num_particles += 1;
+                }
+            });
+
+        // anything past the cap is dropped so that big fights can't tank the framerate
+        let remaining_particles = particle_info.max_particles.saturating_sub(num_particles);
+        queued_particles
+            .0
+            .drain(..)
+            .flat_map(|(pos, effect)| (0..effect.burst_size()).map(move |_| (pos, effect)))
+            .take(remaining_particles)
+            .for_each(|(pos, effect)| {
+                let (pos, vel, particle) = effect.new_particle(pos);
+                entities
+                    .build_entity()
+                    .with(pos, &mut positions)
+                    .with(vel, &mut vels)
+                    .with(particle, &mut particles)
+                    .build();
+            });
+    }
+}
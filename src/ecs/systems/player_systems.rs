@@ -15,7 +15,6 @@ impl<'a> System<'a> for SpawnBulletSys {
         Entities<'a>,
         Read<'a, PlayerEntity>,
         Read<'a, SpriteSheets>,
-        Read<'a, Sounds>,
         Write<'a, QueuedSounds>,
         Read<'a, LazyUpdate>,
     );
@@ -29,7 +28,6 @@ impl<'a> System<'a> for SpawnBulletSys {
             entities,
             player_entity,
             spritesheets,
-            sounds,
             mut queued_sounds,
             lazy_update,
         ): Self::SystemData,
@@ -43,37 +41,41 @@ impl<'a> System<'a> for SpawnBulletSys {
             .0;
 
         if player_data.reload_timer == 0 {
-            if let Some(sound) = sounds.0.get("shoot") {
-                queued_sounds.0.push(sound.clone());
-            } else {
-                log::warn!("error getting shot sound");
-            }
-
             player_data.reload_timer = player_data.reload_speed;
             let player_pos = positions
                 .get(player_entity.0)
                 .expect("error getting player position")
                 .0;
-            let bullet_pos: Point = player_pos + Vector::new(12.0, 5.0);
-            let bullet = new_bullet(
-                player_data.bullet_type,
-                bullet_pos,
-                [0.0, -5.0 + player_vel.y.min(0.0)].into(),
-                DamagesWho::Enemy,
-            );
-
+            queued_sounds
+                .0
+                .push(QueuedSound::at("shoot", player_pos, 0));
+            let bullet_speed = if player_data.focused { 9.0 } else { 5.0 };
+            let forward = bullet_speed - player_vel.y.min(0.0);
             let spritesheet = spritesheets
                 .0
                 .get("bullets")
-                .expect("error getting bullet spritesheet")
-                .clone();
-
-            let entity = entities.create();
-            lazy_update.insert(entity, bullet.0);
-            lazy_update.insert(entity, bullet.1);
-            lazy_update.insert(entity, bullet.2);
-            lazy_update.insert(entity, bullet.3);
-            lazy_update.insert(entity, Sprite::SpriteSheetInstance(spritesheet, bullet.4));
+                .expect("error getting bullet spritesheet");
+
+            player_data
+                .fire_pattern
+                .shots(player_data.focused)
+                .iter()
+                .for_each(|(offset, angle)| {
+                    let bullet = new_bullet(
+                        player_data.bullet_type,
+                        player_pos + player_data.gun + offset,
+                        [forward * angle.sin(), -forward * angle.cos()].into(),
+                        DamagesWho::Enemy,
+                    );
+                    let (sprite, hitbox) = spritesheet_frame(spritesheet, bullet.3);
+
+                    let entity = entities.create();
+                    lazy_update.insert(entity, bullet.0);
+                    lazy_update.insert(entity, hitbox);
+                    lazy_update.insert(entity, bullet.1);
+                    lazy_update.insert(entity, bullet.2);
+                    lazy_update.insert(entity, sprite);
+                });
         }
     }
 }
@@ -90,6 +92,9 @@ impl<'a> System<'a> for PlayerCollSys {
         Entities<'a>,
         Read<'a, PlayerEntity>,
         Read<'a, Dead>,
+        Write<'a, Camera>,
+        Read<'a, GodMode>,
+        Write<'a, FrameEvents>,
     );
 
     fn run(
@@ -104,6 +109,9 @@ impl<'a> System<'a> for PlayerCollSys {
             entities,
             player_entity,
             dead,
+            mut camera,
+            god_mode,
+            mut frame_events,
         ): Self::SystemData,
     ) {
         if dead.0 {
@@ -132,20 +140,22 @@ impl<'a> System<'a> for PlayerCollSys {
         );
         (&mut hp_storage, &positions, &hitboxes, &entities, !&bullets)
             .join()
-            .for_each(|(mut other_hp, pos, hbox, entity, _)| {
+            .for_each(|(other_hp, pos, hbox, entity, _)| {
                 if entity == player_entity.0 || other_hp.iframes > 0 || player_hp.iframes > 0 {
                     return;
                 }
 
                 let other_rect = Rect::new(pos.0.x + hbox.0.x, pos.0.y + hbox.0.y, hbox.1, hbox.2);
                 if other_rect.overlaps(&player_rect) {
-                    if let Some(enemy) = enemies.get(entity) {
+                    if let Some(enemy) = enemies.get(entity).filter(|_| !god_mode.0) {
                         let (damage_to_player, iframes) = match enemy.ty {
                             _ => (1, 30),
                         };
                         player_hp.remaining =
                             (player_hp.remaining as i16 - damage_to_player).max(0) as u32;
                         player_hp.iframes = iframes;
+                        camera.add_trauma(0.3);
+                        frame_events.player_hits.push(DamageSource::Enemy(enemy.ty));
 
                         player_vel.0 += (player_pos - pos.0).normalize() * 20.0;
                     }
@@ -164,40 +174,28 @@ impl<'a> System<'a> for DeflectorSys {
     type SystemData = (
         WriteStorage<'a, Player>,
         WriteStorage<'a, Sprite>,
-        Read<'a, Sprites>,
+        ReadStorage<'a, ShipSprites>,
     );
 
-    fn run(&mut self, (mut players, mut sprite_storage, sprites): Self::SystemData) {
-        (&mut players, &mut sprite_storage)
+    fn run(&mut self, (mut players, mut sprite_storage, ship_sprites): Self::SystemData) {
+        (&mut players, &mut sprite_storage, &ship_sprites)
             .join()
-            .for_each(|(mut player, sprite)| {
+            .for_each(|(player, sprite, ship_sprites)| {
                 if player.deflector_timer > 0 {
                     player.deflector_timer -= 1;
                 }
                 if player.deflector_timer == 1 {
-                    let player_cooldown_sprite = sprites
-                        .0
-                        .get("player_cooldown")
-                        .expect("error getting player cooldown sprite");
-                    *sprite = Sprite::Img(player_cooldown_sprite.clone());
+                    *sprite = Sprite::Img(ship_sprites.cooldown.clone());
                 }
-                if player.deflector_timer == player.deflector_frames - 1 {
-                    let player_deflector_sprite = sprites
-                        .0
-                        .get("player_deflector")
-                        .expect("error getting player deflector sprite");
-                    *sprite = Sprite::Img(player_deflector_sprite.clone());
+                if player.deflector_timer == player.deflector.active_frames.saturating_sub(1) {
+                    *sprite = Sprite::Img(ship_sprites.deflector.clone());
                 }
 
                 if player.deflector_cooldown > 0 {
                     player.deflector_cooldown -= 1;
                 }
                 if player.deflector_cooldown == 1 {
-                    let player_default_sprite = sprites
-                        .0
-                        .get("player")
-                        .expect("error getting player default sprite");
-                    *sprite = Sprite::Img(player_default_sprite.clone());
+                    *sprite = Sprite::Img(ship_sprites.normal.clone());
                 }
             });
     }
@@ -3,6 +3,38 @@ use crate::ecs::components::*;
 use crate::ecs::resources::*;
 use specs::prelude::*;
 
+// how many frames it takes for one stage to cross-fade into the next
+const STAGE_FADE_FRAMES: f32 = 180.0;
+
+fn spawn_stage_stars<'a>(
+    stage: usize,
+    star_info: &StarInfo,
+    entities: &Entities<'a>,
+    stars: &mut WriteStorage<'a, Star>,
+    positions: &mut WriteStorage<'a, Position>,
+    vels: &mut WriteStorage<'a, Velocity>,
+    colorects: &mut WriteStorage<'a, ColorRect>,
+) {
+    star_info
+        .layers(stage)
+        .iter()
+        .enumerate()
+        .for_each(|(layer_index, layer)| {
+            (0..layer.num_stars).for_each(|_| {
+                let mut star = star_info.new_star(stage, layer_index);
+                (star.0).0.y += crate::SCREEN_HEIGHT * 0.9;
+
+                entities
+                    .build_entity()
+                    .with(Star::new(stage, layer_index), stars)
+                    .with(star.0, positions)
+                    .with(star.1, vels)
+                    .with(star.2, colorects)
+                    .build();
+            });
+        });
+}
+
 #[derive(Default)]
 pub struct StarInitSys;
 impl<'a> System<'a> for StarInitSys {
@@ -19,17 +51,85 @@ impl<'a> System<'a> for StarInitSys {
         &mut self,
         (entities, mut stars, mut positions, mut vels, mut colorects, star_info): Self::SystemData,
     ) {
-        (0..star_info.num_stars).for_each(|_| {
-            let mut star = star_info.new_star();
-            (star.0).0.y += crate::SCREEN_HEIGHT * 0.9;
-
-            entities
-                .build_entity()
-                .with(Star::default(), &mut stars)
-                .with(star.0, &mut positions)
-                .with(star.1, &mut vels)
-                .with(star.2, &mut colorects)
-                .build();
+        spawn_stage_stars(
+            star_info.current_stage,
+            &star_info,
+            &entities,
+            &mut stars,
+            &mut positions,
+            &mut vels,
+            &mut colorects,
+        );
+    }
+}
+
+pub struct StarStageSys;
+impl<'a> System<'a> for StarStageSys {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Star>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Velocity>,
+        WriteStorage<'a, ColorRect>,
+        Write<'a, StarInfo>,
+        Read<'a, CurrentWave>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut stars,
+            mut positions,
+            mut vels,
+            mut colorects,
+            mut star_info,
+            current_wave,
+        ): Self::SystemData,
+    ) {
+        // the new stage's stars are spawned right away and fade in,
+        // the old ones fade out and get deleted in the StarMoveSys
+        let stage = star_info.stage_for_wave(current_wave.0);
+        if stage != star_info.current_stage {
+            star_info.previous_stage = star_info.current_stage;
+            star_info.current_stage = stage;
+            star_info.fade = 0.0;
+
+            spawn_stage_stars(
+                stage,
+                &star_info,
+                &entities,
+                &mut stars,
+                &mut positions,
+                &mut vels,
+                &mut colorects,
+            );
+        }
+        star_info.fade = (star_info.fade + 1.0 / STAGE_FADE_FRAMES).min(1.0);
+
+        // background images scroll at their layer's speed, the outgoing stage keeps
+        // scrolling with its own offsets until it has faded out
+        let mut scrolling = vec![star_info.current_stage];
+        if star_info.fade < 1.0 {
+            scrolling.push(star_info.previous_stage);
+        }
+        let num_stages = star_info.stages.len();
+        star_info.scroll.resize(num_stages, Vec::new());
+        scrolling.into_iter().for_each(|stage| {
+            let layer_vels: Vec<f32> = star_info
+                .layers(stage)
+                .iter()
+                .map(|layer| layer.vel)
+                .collect();
+            if let Some(scroll) = star_info.scroll.get_mut(stage) {
+                scroll.resize(layer_vels.len(), 0.0);
+                scroll
+                    .iter_mut()
+                    .zip(layer_vels.iter())
+                    .for_each(|(scroll, vel)| {
+                        *scroll = (*scroll + vel) % crate::SCREEN_HEIGHT;
+                    });
+            }
         });
     }
 }
@@ -37,7 +137,8 @@ impl<'a> System<'a> for StarInitSys {
 pub struct StarMoveSys;
 impl<'a> System<'a> for StarMoveSys {
     type SystemData = (
-        ReadStorage<'a, Star>,
+        Entities<'a>,
+        WriteStorage<'a, Star>,
         WriteStorage<'a, Position>,
         WriteStorage<'a, Velocity>,
         WriteStorage<'a, ColorRect>,
@@ -46,17 +147,45 @@ impl<'a> System<'a> for StarMoveSys {
 
     fn run(
         &mut self,
-        (stars, mut positions, mut vels, mut colorects, star_info): Self::SystemData,
+        (entities, mut stars, mut positions, mut vels, mut colorects, star_info): Self::SystemData,
     ) {
-        (&stars, &mut positions, &mut vels, &mut colorects)
+        (
+            &mut stars,
+            &mut positions,
+            &mut vels,
+            &mut colorects,
+            &entities,
+        )
             .par_join()
-            .for_each(|(_, pos, vel, colorect)| {
+            .for_each(|(star, pos, vel, colorect, entity)| {
+                let fading_out = star.stage != star_info.current_stage;
+                let faded_out =
+                    fading_out && (star_info.fade >= 1.0 || pos.0.y > crate::SCREEN_HEIGHT);
+                let layer = match star_info.layers(star.stage).get(star.layer) {
+                    Some(layer) if !faded_out => layer,
+                    _ => {
+                        if entities.delete(entity).is_err() {
+                            log::warn!("error deleting faded out star");
+                        }
+                        return;
+                    }
+                };
+
                 if pos.0.y > crate::SCREEN_HEIGHT {
-                    let (npos, nvel, ncolorect) = star_info.new_star();
+                    let (npos, nvel, ncolorect) = star_info.new_star(star.stage, star.layer);
                     *pos = npos;
                     *vel = nvel;
                     *colorect = ncolorect;
                 }
+
+                star.twinkle_phase += 0.08;
+                let twinkle = 1.0 - layer.twinkle * (0.5 + 0.5 * star.twinkle_phase.sin());
+                let fade = if fading_out {
+                    1.0 - star_info.fade
+                } else {
+                    star_info.fade
+                };
+                colorect.color.a = layer.color.a * twinkle * fade;
             });
     }
 }
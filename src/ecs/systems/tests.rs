@@ -0,0 +1,593 @@
+use super::*;
+use crate::ecs::components::*;
+use crate::ecs::resources::*;
+use crate::sim;
+use specs::prelude::*;
+
+// a world with every component registered and the shipped assets loaded headless. players and
+// enemies are built by hand, bullets get their hitboxes from the bullet atlas like in the game
+fn test_world() -> World {
+    let mut world = sim::create_world();
+    let errors =
+        crate::assets::load_headless(std::path::Path::new(crate::RESOURCES_DIR), &mut world);
+    assert!(errors.is_empty(), "error loading assets: {:?}", errors);
+    world
+}
+
+fn run<S>(world: &mut World, mut system: S)
+where
+    S: for<'a> RunNow<'a>,
+{
+    system.setup(world);
+    system.run_now(world);
+    world.maintain();
+}
+
+fn spawn_player(world: &mut World, pos: Point) -> Entity {
+    let player = world
+        .create_entity()
+        .with(Player::default())
+        .with(Position(pos))
+        .with(Velocity(Vector::new(0.0, 0.0)))
+        .with(HP::new(5))
+        .with(Hitbox(Point::new(0.0, 0.0), 45.0, 45.0))
+        .build();
+    world.insert(PlayerEntity(player));
+    player
+}
+
+fn spawn_enemy(world: &mut World, pos: Point, hp: u32) -> Entity {
+    world
+        .create_entity()
+        .with(Position(pos))
+        .with(HP::new(hp))
+        .with(Hitbox(Point::new(0.0, 0.0), 55.0, 43.0))
+        .build()
+}
+
+fn spawn_bullet(
+    world: &mut World,
+    ty: BulletType,
+    pos: Point,
+    vel: Vector,
+    damages_who: DamagesWho,
+) -> Entity {
+    let (pos, vel, bullet, frame) = new_bullet(ty, pos, vel, damages_who);
+    let spritesheet = world.fetch::<SpriteSheets>().0["bullets"].clone();
+    let (_, hitbox) = spritesheet_frame(&spritesheet, frame);
+    world
+        .create_entity()
+        .with(pos)
+        .with(hitbox)
+        .with(vel)
+        .with(bullet)
+        .build()
+}
+
+#[test]
+fn bullet_coll_sys_deletes_bullets_overlapping_enemies() {
+    let mut world = test_world();
+    spawn_player(&mut world, Point::new(400.0, 700.0));
+    let enemy = spawn_enemy(&mut world, Point::new(100.0, 100.0), 5);
+    let hit = spawn_bullet(
+        &mut world,
+        BulletType::PlayerBullet,
+        Point::new(110.0, 120.0),
+        Vector::new(0.0, -5.0),
+        DamagesWho::Enemy,
+    );
+    let missed = spawn_bullet(
+        &mut world,
+        BulletType::PlayerBullet,
+        Point::new(500.0, 300.0),
+        Vector::new(0.0, -5.0),
+        DamagesWho::Enemy,
+    );
+
+    run(&mut world, BulletCollSys);
+
+    assert!(!world.is_alive(hit));
+    assert!(world.is_alive(missed));
+    assert_eq!(world.read_storage::<HP>().get(enemy).unwrap().remaining, 2);
+}
+
+#[test]
+fn deflector_reflects_bullets_and_triples_their_damage() {
+    let mut world = test_world();
+    let player = spawn_player(&mut world, Point::new(300.0, 300.0));
+    world
+        .write_storage::<Player>()
+        .get_mut(player)
+        .unwrap()
+        .deflector_timer = 6;
+    let bullet = spawn_bullet(
+        &mut world,
+        BulletType::BasicBullet,
+        Point::new(310.0, 300.0),
+        Vector::new(0.0, 8.0),
+        DamagesWho::Player,
+    );
+
+    run(&mut world, BulletCollSys);
+
+    assert!(world.is_alive(bullet));
+    let vel = world.read_storage::<Velocity>().get(bullet).unwrap().0;
+    assert!(
+        vel.y < 0.0,
+        "the bullet should go back up, its velocity is {}",
+        vel
+    );
+    let bullets = world.read_storage::<Bullet>();
+    let deflected = bullets.get(bullet).unwrap();
+    assert_eq!(deflected.damage, 3);
+    assert_eq!(deflected.damages_who, DamagesWho::Enemy);
+    assert!(world.read_storage::<Deflected>().get(bullet).is_some());
+    assert_eq!(world.read_storage::<HP>().get(player).unwrap().remaining, 5);
+}
+
+#[test]
+fn bullets_damage_the_player_without_the_deflector() {
+    let mut world = test_world();
+    let player = spawn_player(&mut world, Point::new(300.0, 300.0));
+    let bullet = spawn_bullet(
+        &mut world,
+        BulletType::BasicBullet,
+        Point::new(310.0, 300.0),
+        Vector::new(0.0, 8.0),
+        DamagesWho::Player,
+    );
+
+    run(&mut world, BulletCollSys);
+
+    assert!(!world.is_alive(bullet));
+    assert_eq!(world.read_storage::<HP>().get(player).unwrap().remaining, 4);
+}
+
+#[test]
+fn hp_kill_sys_sets_dead_when_the_player_has_no_hp() {
+    let mut world = test_world();
+    let player = spawn_player(&mut world, Point::new(300.0, 300.0));
+    let enemy = spawn_enemy(&mut world, Point::new(100.0, 100.0), 3);
+
+    run(&mut world, HPKillSys);
+    assert!(!world.fetch::<Dead>().0);
+    assert!(world.is_alive(enemy));
+
+    world
+        .write_storage::<HP>()
+        .get_mut(player)
+        .unwrap()
+        .remaining = 0;
+    run(&mut world, HPKillSys);
+    assert!(world.fetch::<Dead>().0);
+    assert!(!world.is_alive(player));
+    assert!(world.is_alive(enemy));
+
+    // entities without a position or hitbox die too, just without debris
+    let bare = world.create_entity().with(HP::new(0)).build();
+    run(&mut world, HPKillSys);
+    assert!(!world.is_alive(bare));
+}
+
+#[test]
+fn bounce_bullet_sys_reduces_bounces_at_the_edges() {
+    let mut world = test_world();
+    let bouncing = spawn_bullet(
+        &mut world,
+        BulletType::BouncingBullet(2),
+        Point::new(crate::SCREEN_WIDTH + 5.0, 300.0),
+        Vector::new(4.0, 4.0),
+        DamagesWho::Player,
+    );
+    let overbounced = spawn_bullet(
+        &mut world,
+        BulletType::BouncingBullet(0),
+        Point::new(-5.0, 300.0),
+        Vector::new(-4.0, 4.0),
+        DamagesWho::Player,
+    );
+    let inside = spawn_bullet(
+        &mut world,
+        BulletType::BouncingBullet(2),
+        Point::new(300.0, 300.0),
+        Vector::new(4.0, 4.0),
+        DamagesWho::Player,
+    );
+
+    run(&mut world, BounceBulletSys);
+
+    let bullets = world.read_storage::<Bullet>();
+    let vels = world.read_storage::<Velocity>();
+    assert_eq!(
+        bullets.get(bouncing).unwrap().ty,
+        BulletType::BouncingBullet(1)
+    );
+    assert!(vels.get(bouncing).unwrap().0.x < 0.0);
+    assert!(!world.is_alive(overbounced));
+    assert_eq!(
+        bullets.get(inside).unwrap().ty,
+        BulletType::BouncingBullet(2)
+    );
+    assert!(vels.get(inside).unwrap().0.x > 0.0);
+}
+
+#[test]
+fn wave_calc_sys_stays_within_the_difficulty_target() {
+    let mut world = test_world();
+    DIFFICULTIES.iter().for_each(|difficulty| {
+        world.insert(*difficulty);
+        (1..=40).for_each(|wave| {
+            world.insert(CurrentWave(wave));
+            run(&mut world, WaveCalcSys);
+
+            // each enemy counts double, and the last one can only go over by less than it's worth
+            let balance = world.fetch::<Balance>();
+            let budget = balance.wave_budget(wave, *difficulty);
+            let total: u16 = world
+                .fetch::<QueuedEnemies>()
+                .0
+                .iter()
+                .map(|(_, ty)| balance.enemy(*ty).cost * 2)
+                .sum();
+            assert!(
+                total >= budget && total - budget <= (budget / 4).max(1),
+                "wave {} on {:?} has {} difficulty for a budget of {}",
+                wave,
+                difficulty,
+                total,
+                budget
+            );
+        });
+    });
+}
+
+#[test]
+fn wave_budget_grows_with_the_difficulty() {
+    let balance = Balance::default();
+    (1..=40).for_each(|wave| {
+        let budgets: Vec<u16> = DIFFICULTIES
+            .iter()
+            .map(|difficulty| balance.wave_budget(wave, *difficulty))
+            .collect();
+        assert!(
+            budgets.windows(2).all(|pair| pair[0] <= pair[1]),
+            "wave {} budgets {:?} aren't in order of difficulty",
+            wave,
+            budgets
+        );
+    });
+}
+
+#[test]
+fn the_balance_file_matches_the_default_balance() {
+    let balance = crate::assets::balance_from_file(std::path::Path::new(crate::RESOURCES_DIR))
+        .expect("error loading balance");
+    assert_eq!(balance, Balance::default());
+}
+
+#[test]
+fn iframe_sys_counts_down_to_zero() {
+    let mut world = test_world();
+    let entity = spawn_enemy(&mut world, Point::new(100.0, 100.0), 3);
+    world.write_storage::<HP>().get_mut(entity).unwrap().iframes = 2;
+
+    let iframes: Vec<u8> = (0..3)
+        .map(|_| {
+            run(&mut world, IFrameSys);
+            world.read_storage::<HP>().get(entity).unwrap().iframes
+        })
+        .collect();
+    assert_eq!(iframes, vec![1, 0, 0]);
+}
+
+#[test]
+fn rank_sys_counts_hits_and_deflections_while_enemies_are_up() {
+    let mut world = test_world();
+    world.insert(Rank::new(true));
+    world.fetch_mut::<Rank>().start_wave(30);
+    world.insert(FrameEvents {
+        player_hits: vec![DamageSource::Bullet(BulletType::AimedBullet)],
+        deflections: 2,
+        ..FrameEvents::default()
+    });
+
+    run(&mut world, RankSys);
+    assert_eq!(world.fetch::<Rank>().wave.frames, 0);
+
+    let (_, _, enemy, _, _) = new_enemy(
+        EnemyType::BasicEnemy,
+        Point::new(100.0, 100.0),
+        MovementType::horizontal(100.0, 75.0, 1.0),
+        &world.fetch::<Balance>(),
+        &mut world.fetch_mut::<GameRng>().0,
+    );
+    world.create_entity().with(enemy).build();
+    run(&mut world, RankSys);
+
+    let wave = world.fetch::<Rank>().wave;
+    assert_eq!(wave.hits, 2);
+    assert_eq!(wave.deflections, 4);
+    assert_eq!(wave.frames, 1);
+    assert_eq!(wave.enemy_hp, 30);
+}
+
+#[test]
+fn rank_rises_after_clean_waves_and_falls_after_bad_ones_within_bounds() {
+    let mut rank = Rank::new(true);
+    (0..20).for_each(|_| {
+        rank.start_wave(30);
+        rank.wave.frames = 100;
+        rank.end_wave(6, 6);
+    });
+    assert_eq!(rank.value, RANK_MAX);
+
+    (0..20).for_each(|_| {
+        rank.start_wave(30);
+        rank.wave.hits = 3;
+        rank.wave.frames = 1000;
+        rank.end_wave(1, 6);
+    });
+    assert_eq!(rank.value, RANK_MIN);
+
+    let mut disabled = Rank::new(false);
+    disabled.start_wave(30);
+    disabled.end_wave(6, 6);
+    assert_eq!(disabled.value, 1.0);
+}
+
+#[test]
+fn bullets_passing_close_to_the_player_are_grazed_once() {
+    let mut world = test_world();
+    let player = spawn_player(&mut world, Point::new(300.0, 300.0));
+    world
+        .write_storage::<Player>()
+        .get_mut(player)
+        .unwrap()
+        .deflector_cooldown = 100;
+    // place the bullet from the atlas' hitbox so it passes 5 right of the player's 45x45 box
+    let spritesheet = world.fetch::<SpriteSheets>().0["bullets"].clone();
+    let (_, Hitbox(offset, _, height)) = spritesheet_frame(&spritesheet, "basic");
+    let close = spawn_bullet(
+        &mut world,
+        BulletType::BasicBullet,
+        Point::new(350.0 - offset.x, 322.5 - offset.y - height / 2.0),
+        Vector::new(0.0, 8.0),
+        DamagesWho::Player,
+    );
+    let far = spawn_bullet(
+        &mut world,
+        BulletType::BasicBullet,
+        Point::new(500.0, 316.0),
+        Vector::new(0.0, 8.0),
+        DamagesWho::Player,
+    );
+
+    // nothing counts while the bullet is still close, it could still hit
+    run(&mut world, BulletCollSys);
+    run(&mut world, BulletCollSys);
+    assert!(world.read_storage::<Grazing>().get(close).is_some());
+    assert_eq!(world.fetch::<GrazeCount>().0, 0);
+
+    world
+        .write_storage::<Position>()
+        .get_mut(close)
+        .unwrap()
+        .0
+        .y = 400.0;
+    run(&mut world, BulletCollSys);
+    run(&mut world, BulletCollSys);
+
+    assert!(world.read_storage::<Grazing>().get(close).is_none());
+    assert!(world.read_storage::<Grazed>().get(close).is_some());
+    assert!(world.read_storage::<Grazed>().get(far).is_none());
+    assert_eq!(world.fetch::<GrazeCount>().0, 1);
+    assert!(world.fetch::<Score>().current > 0);
+    assert!(
+        world
+            .read_storage::<Player>()
+            .get(player)
+            .unwrap()
+            .deflector_cooldown
+            < 100
+    );
+    assert_eq!(world.read_storage::<HP>().get(player).unwrap().remaining, 5);
+}
+
+#[test]
+fn bullets_that_hit_the_player_are_not_grazed() {
+    let mut world = test_world();
+    let player = spawn_player(&mut world, Point::new(300.0, 300.0));
+    let bullet = spawn_bullet(
+        &mut world,
+        BulletType::BasicBullet,
+        Point::new(315.0, 250.0),
+        Vector::new(0.0, 8.0),
+        DamagesWho::Player,
+    );
+
+    (0..20).for_each(|_| {
+        run(&mut world, BulletCollSys);
+        run(&mut world, IntegrateSys);
+    });
+
+    assert!(!world.entities().is_alive(bullet));
+    assert_eq!(world.read_storage::<HP>().get(player).unwrap().remaining, 4);
+    assert_eq!(world.fetch::<GrazeCount>().0, 0);
+}
+
+#[test]
+fn holding_deflect_charges_the_deflector_until_let_go() {
+    let mut world = test_world();
+    let player = spawn_player(&mut world, Point::new(300.0, 300.0));
+    let deflector = Deflector::default();
+
+    (0..deflector.level_frames * (deflector.max_level + 2))
+        .for_each(|_| sim::charge_deflector(&mut world, true));
+    {
+        let players = world.read_storage::<Player>();
+        let charging = players.get(player).unwrap();
+        assert_eq!(charging.charge_level(), deflector.max_level);
+        assert_eq!(charging.deflector_timer, 0);
+    }
+
+    sim::charge_deflector(&mut world, false);
+    let players = world.read_storage::<Player>();
+    let released = players.get(player).unwrap();
+    assert_eq!(released.deflector_level, deflector.max_level);
+    assert_eq!(released.deflector_timer, deflector.active_frames);
+    assert_eq!(
+        released.deflector_cooldown,
+        deflector.reload_frames + deflector.level_cooldown * (deflector.max_level - 1)
+    );
+    assert_eq!(released.deflector_held_frames, 0);
+}
+
+#[test]
+fn charged_deflector_reaches_further_and_aims_at_the_nearest_enemy() {
+    let mut world = test_world();
+    let player = spawn_player(&mut world, Point::new(300.0, 300.0));
+    {
+        let mut players = world.write_storage::<Player>();
+        let player = players.get_mut(player).unwrap();
+        player.deflector_timer = 6;
+        player.deflector_level = 3;
+    }
+    let mut rng = GameRng::seeded(0).0;
+    [Point::new(100.0, 100.0), Point::new(700.0, 50.0)]
+        .iter()
+        .for_each(|pos| {
+            let enemy = spawn_enemy(&mut world, *pos, 5);
+            let (_, _, enemy_data, _, _) = new_enemy(
+                EnemyType::BasicEnemy,
+                *pos,
+                MovementType::horizontal(pos.x, 0.0, 0.0),
+                &Balance::default(),
+                &mut rng,
+            );
+            world
+                .write_storage::<Enemy>()
+                .insert(enemy, enemy_data)
+                .unwrap();
+        });
+    // left of the player's hitbox, but in reach of a level 3 deflector
+    let bullet = spawn_bullet(
+        &mut world,
+        BulletType::BasicBullet,
+        Point::new(260.0, 300.0),
+        Vector::new(0.0, 8.0),
+        DamagesWho::Player,
+    );
+
+    run(&mut world, BulletCollSys);
+
+    let vel = world.read_storage::<Velocity>().get(bullet).unwrap().0;
+    // bullets are spawned 16 up, so its center is at 272.5, 296.5
+    let to_enemy = Point::new(127.5, 121.5) - Point::new(272.5, 296.5);
+    assert!(
+        (vel.normalize() - to_enemy.normalize()).norm() < 0.01,
+        "the bullet should go towards the closest enemy, its velocity is {}",
+        vel
+    );
+    assert_eq!(
+        world.read_storage::<Bullet>().get(bullet).unwrap().damage,
+        5
+    );
+    assert_eq!(
+        world.read_storage::<Deflected>().get(bullet).copied(),
+        Some(Deflected(2))
+    );
+}
+
+#[test]
+fn players_spawn_as_the_ship_in_the_world() {
+    let mut world = test_world();
+    let ship = Ship {
+        speed: 1.5,
+        hitbox: (10.0, 12.0, 20.0, 22.0),
+        fire_pattern: FirePattern::Spread,
+        thruster: (20.0, 40.0),
+        extra_hp: 2,
+        sprites: ShipSprites {
+            normal: "wasp".to_string(),
+            deflector: "wasp_deflector".to_string(),
+            cooldown: "wasp_cooldown".to_string(),
+        },
+        ..Ship::default()
+    };
+    world.insert(ship.clone());
+    sim::spawn_player(&mut world, 5);
+    let player_entity = world.fetch::<PlayerEntity>().0;
+
+    let players = world.read_storage::<Player>();
+    let player = players.get(player_entity).unwrap();
+    assert_eq!(player.speed, 1.5);
+    assert_eq!(player.fire_pattern, FirePattern::Spread);
+    assert_eq!(
+        world.read_storage::<Hitbox>().get(player_entity).copied(),
+        Some(Hitbox(Point::new(10.0, 12.0), 20.0, 22.0))
+    );
+    assert_eq!(
+        world
+            .read_storage::<HP>()
+            .get(player_entity)
+            .unwrap()
+            .remaining,
+        7
+    );
+    assert_eq!(
+        world.read_storage::<ShipSprites>().get(player_entity),
+        Some(&ship.sprites)
+    );
+    assert_eq!(
+        world
+            .read_storage::<ParticleEmitter>()
+            .get(player_entity)
+            .unwrap()
+            .offset,
+        Vector::new(20.0, 40.0)
+    );
+}
+
+#[test]
+fn deflector_sys_switches_between_the_ships_sprites() {
+    let mut world = test_world();
+    world.insert(Ship {
+        sprites: ShipSprites {
+            normal: "wasp".to_string(),
+            deflector: "wasp_deflector".to_string(),
+            cooldown: "wasp_cooldown".to_string(),
+        },
+        ..Ship::default()
+    });
+    sim::spawn_player(&mut world, 5);
+    let player_entity = world.fetch::<PlayerEntity>().0;
+    sim::charge_deflector(&mut world, true);
+    sim::charge_deflector(&mut world, false);
+
+    let sprite = |world: &World| match world.read_storage::<Sprite>().get(player_entity) {
+        Some(Sprite::Img(key)) => key.clone(),
+        _ => panic!("the player should have a sprite"),
+    };
+    run(&mut world, DeflectorSys);
+    assert_eq!(sprite(&world), "wasp_deflector");
+    while world
+        .read_storage::<Player>()
+        .get(player_entity)
+        .unwrap()
+        .deflector_timer
+        > 0
+    {
+        run(&mut world, DeflectorSys);
+    }
+    assert_eq!(sprite(&world), "wasp_cooldown");
+    while world
+        .read_storage::<Player>()
+        .get(player_entity)
+        .unwrap()
+        .deflector_cooldown
+        > 0
+    {
+        run(&mut world, DeflectorSys);
+    }
+    assert_eq!(sprite(&world), "wasp");
+}
@@ -1,30 +1,80 @@
 use ggez::{
     event::EventHandler,
-    graphics::{self, Color, DrawMode, DrawParam, MeshBuilder, Rect},
-    input::{self, keyboard::KeyCode, keyboard::KeyMods},
+    graphics::{self, DrawParam},
+    input::{keyboard::KeyCode, keyboard::KeyMods},
     Context, GameResult,
 };
 use specs::prelude::*;
 
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::audio::Mixer;
+use crate::debug::DebugTools;
 use crate::ecs::components::*;
 use crate::ecs::resources::*;
 use crate::ecs::systems;
-
-use rand::prelude::*;
+use crate::menu::{MenuAction, PauseMenu, ShipSelect};
+use crate::save::SaveData;
+use crate::screen::PixelScreen;
+use crate::settings::{Action, Settings};
+use crate::sim;
 
 pub struct GameState<'a, 'b> {
     world: World,
     dispatcher: Dispatcher<'a, 'b>,
+    mixer: Mixer,
+    settings: Settings,
+    screen: PixelScreen,
+    // the game is paused while the menu is open
+    pause_menu: Option<PauseMenu>,
+    // open before the first run and after dying, also pausing the game
+    ship_select: Option<ShipSelect>,
+    // set when deflect goes down, so that a tap let go before the next update still deflects
+    deflect_pressed: bool,
+    debug: DebugTools,
+    // the system timings are written here when the game closes, see `--profile`
+    profile_output: Option<PathBuf>,
+    #[cfg(feature = "hot_reload")]
+    hot_reloader: Option<crate::hot_reload::HotReloader>,
 }
 
 impl<'a, 'b> GameState<'a, 'b> {
-    pub fn new(mut world: World, dispatcher: Dispatcher<'a, 'b>) -> Self {
-        // the init star sys only runs once; afterwards the StarMoveSys handles everything
+    pub fn new(
+        ctx: &mut Context,
+        mut world: World,
+        dispatcher: Dispatcher<'a, 'b>,
+        mixer: Mixer,
+        settings: Settings,
+    ) -> GameResult<Self> {
+        // the init star sys only runs once; afterwards the StarStageSys and StarMoveSys handle everything
         let mut init_star_sys = systems::StarInitSys::default();
         specs::RunNow::setup(&mut init_star_sys, &mut world);
         init_star_sys.run_now(&world);
 
-        GameState { world, dispatcher }
+        // with only one ship there's nothing to pick
+        let ship_select = if world.fetch::<Ships>().0.len() > 1 {
+            Some(ShipSelect::new(&world.fetch::<Ships>(), &settings.ship))
+        } else {
+            None
+        };
+
+        Ok(GameState {
+            world,
+            dispatcher,
+            mixer,
+            settings,
+            screen: PixelScreen::new(ctx)?,
+            pause_menu: None,
+            ship_select,
+            deflect_pressed: false,
+            debug: DebugTools::default(),
+            profile_output: None,
+            #[cfg(feature = "hot_reload")]
+            hot_reloader: crate::hot_reload::HotReloader::new(std::path::Path::new(
+                crate::RESOURCES_DIR,
+            )),
+        })
     }
 }
 
@@ -52,368 +102,124 @@ impl EventHandler for GameState<'_, '_> {
             return Ok(());
         }
 
-        let dead = self.world.fetch::<Dead>().0;
-
-        // player shooting
-        if !dead && input::keyboard::is_key_pressed(ctx, KeyCode::Space) {
-            let mut spawn_sys = systems::SpawnBulletSys::default();
-            spawn_sys.run_now(&self.world);
-        }
-
+        #[cfg(feature = "hot_reload")]
         {
-            // play all queued sounds
-            use ggez::audio::Source;
+            if let Some(hot_reloader) = &mut self.hot_reloader {
+                hot_reloader.update(ctx, &mut self.world);
+            }
+        }
 
-            self.world
-                .fetch_mut::<QueuedSounds>()
-                .0
-                .drain(..)
-                .for_each(|sound_data| {
-                    use ggez::audio::SoundSource;
-
-                    if let Ok(mut source) = Source::from_data(ctx, sound_data) {
-                        source.set_volume(0.2 * crate::VOLUME_MULTIPLIER);
-                        if source.play_detached().is_err() {
-                            log::warn!("Error playing sound");
-                        }
-                    } else {
-                        log::warn!("Error initializing sound source")
-                    }
-                });
+        self.mixer.update();
+        if self.pause_menu.is_some() || self.ship_select.is_some() || self.debug.console_open {
+            return Ok(());
         }
 
+        let dead = self.world.fetch::<Dead>().0;
+
         {
-            // update hp text if it has changed
-            let hp_text = &mut self.world.fetch_mut::<HPText>();
-            if hp_text.needs_redraw {
-                hp_text.needs_redraw = false;
-
-                let hp = if dead {
-                    0
-                } else {
-                    let player = self.world.fetch::<PlayerEntity>().0;
-                    self.world
-                        .read_storage::<HP>()
-                        .get(player)
-                        .expect("Error fetching player hp")
-                        .remaining
-                };
-
-                let wave = self.world.fetch::<CurrentWave>().0;
-
-                *hp_text.text.lock().expect("error locking hp_text") = {
-                    use ggez::graphics::Scale;
-                    let font = self.world.fetch::<GameFont>().0;
-                    let mut text = graphics::Text::new(format!("     x {}\nWave: {}", hp, wave));
-                    text.set_font(font, Scale::uniform(48.0));
-                    text
-                };
-            }
+            // play all queued sounds
+            let queued_sounds: Vec<QueuedSound> =
+                self.world.fetch_mut::<QueuedSounds>().0.drain(..).collect();
+            self.mixer.play_queued(
+                ctx,
+                &queued_sounds,
+                &self.world.fetch::<Sounds>(),
+                &self.world.fetch::<SoundConfigs>(),
+            );
         }
 
         {
-            // spawn new enemies if all enemies are dead
-            let num_enemies = {
-                let enemies = self.world.read_storage::<Enemy>();
-                enemies.join().count()
-            };
-            if num_enemies == 0 {
-                let frames_to_next_wave = &mut self.world.fetch_mut::<FramesToNextWave>().0;
-                if *frames_to_next_wave != 0 {
-                    *frames_to_next_wave -= 1;
-                } else {
-                    self.world.fetch_mut::<HPText>().needs_redraw = true;
-                    {
-                        let current_wave = &mut self.world.fetch_mut::<CurrentWave>().0;
-                        *current_wave += 1;
+            // the music director picks the track, the mixer fades between them
+            let desired_music = &self.world.fetch::<DesiredMusic>().0;
+            if desired_music.as_deref() != self.mixer.current_music() {
+                let music = &self.world.fetch::<Music>().0;
+                let sound_configs = self.world.fetch::<SoundConfigs>();
+                match desired_music {
+                    Some(key) => {
+                        // unknown tracks play as silence, the mixer warns about them once
+                        let data = music
+                            .get(key)
+                            .cloned()
+                            .unwrap_or_else(|| ggez::audio::SoundData::from_bytes(&[]));
+                        self.mixer
+                            .play_music(ctx, key, &data, sound_configs.get(key));
                     }
-
-                    {
-                        let mut wave_calc_sys = systems::WaveCalcSys::default();
-                        wave_calc_sys.run_now(&self.world);
-                    }
-
-                    let queued_enemies = &self.world.fetch::<QueuedEnemies>().0;
-                    let mut positions = self.world.write_storage::<Position>();
-                    let mut vels = self.world.write_storage::<Velocity>();
-                    let mut enemies = self.world.write_storage::<Enemy>();
-                    let mut hp_storage = self.world.write_storage::<HP>();
-                    let mut hitboxes = self.world.write_storage::<Hitbox>();
-                    let mut sprites = self.world.write_storage::<Sprite>();
-                    let spritesheets = &self.world.fetch::<SpriteSheets>().0;
-                    let spritesheet = spritesheets
-                        .get("enemies")
-                        .expect("error getting enemy spritesheet")
-                        .clone();
-
-                    if self.world.fetch::<CurrentWave>().0 != 1 {
-                        if let Some(mut player_hp) =
-                            hp_storage.get_mut(self.world.fetch::<PlayerEntity>().0)
-                        {
-                            player_hp.remaining += 1;
-                        }
-                    }
-
-                    let mut rng = rand::thread_rng();
-                    queued_enemies.iter().for_each(|(pos, et)| {
-                        let (mt1, mt2) = {
-                            let mt = rng.gen_range(0, 2);
-                            let mt2_x = crate::SCREEN_WIDTH - 90.0 - pos.x;
-                            match mt {
-                                0 => (
-                                    MovementType::horizontal(pos.x, 75.0, 1.0),
-                                    MovementType::horizontal(mt2_x, 75.0, 1.0),
-                                ),
-                                1 => (
-                                    MovementType::vertical(pos.y, 90.0, 1.0),
-                                    MovementType::vertical(pos.y, 90.0, 1.0),
-                                ),
-                                // 2 => (MovementType::circle(*pos, 60.0, 1.0), MovementType::circle(Point::new(mt2_x, pos.y), 60.0, 1.0)),
-                                _ => unreachable!(),
-                            }
-                        };
-                        let mut enemy = new_enemy(*et, *pos, mt1);
-
-                        self.world
-                            .entities()
-                            .build_entity()
-                            .with(enemy.0, &mut positions)
-                            .with(enemy.1, &mut vels)
-                            .with(enemy.2.clone(), &mut enemies)
-                            .with(enemy.3, &mut hp_storage)
-                            .with(enemy.4, &mut hitboxes)
-                            .with(
-                                Sprite::SpriteSheetInstance(spritesheet.clone(), enemy.5),
-                                &mut sprites,
-                            )
-                            .build();
-
-                        let pos_2 =
-                            Point::new(crate::SCREEN_WIDTH - 90.0 - (enemy.0).0.x, (enemy.0).0.y);
-                        enemy.2.movement = mt2;
-
-                        self.world
-                            .entities()
-                            .build_entity()
-                            .with(Position(pos_2), &mut positions)
-                            .with(enemy.1, &mut vels)
-                            .with(enemy.2, &mut enemies)
-                            .with(enemy.3, &mut hp_storage)
-                            .with(enemy.4, &mut hitboxes)
-                            .with(
-                                Sprite::SpriteSheetInstance(spritesheet.clone(), enemy.5),
-                                &mut sprites,
-                            )
-                            .build();
-                    });
+                    None => self.mixer.stop_music(),
                 }
-            } else if self.world.fetch::<FramesToNextWave>().0 == 0 {
-                self.world.insert(FramesToNextWave::default());
             }
         }
 
-        if !dead {
-            // player movement controls
-            let player_entity = self.world.fetch::<PlayerEntity>().0;
-            let velocities = &mut self.world.write_storage::<Velocity>();
-            let positions = &mut self.world.write_storage::<Position>();
-            let player_vel = &mut velocities
-                .get_mut(player_entity)
-                .expect("error getting player_vel")
-                .0;
-            let player_pos = &mut positions
-                .get_mut(player_entity)
-                .expect("error getting player pos")
-                .0;
-
-            *player_vel /= 1.45;
-
-            let speed = if input::keyboard::is_key_pressed(ctx, KeyCode::Space) {
-                1.3
-            } else {
-                1.7
-            };
+        if !self.debug.step() {
+            return Ok(());
+        }
 
-            if input::keyboard::is_key_pressed(ctx, KeyCode::W) && player_pos.y > 0.0 {
-                player_vel.y -= speed;
-            }
-            if input::keyboard::is_key_pressed(ctx, KeyCode::S)
-                && player_pos.y < crate::SCREEN_HEIGHT - 45.0
-            {
-                player_vel.y += speed;
-            }
-            if input::keyboard::is_key_pressed(ctx, KeyCode::A) && player_pos.x > 0.0 {
-                player_vel.x -= speed;
-            }
-            if input::keyboard::is_key_pressed(ctx, KeyCode::D)
-                && player_pos.x < crate::SCREEN_WIDTH - 45.0
-            {
-                player_vel.x += speed;
+        {
+            // hit-stop freezes everything for a few frames
+            let hit_stop_frames = &mut self.world.fetch_mut::<Camera>().hit_stop_frames;
+            if *hit_stop_frames > 0 {
+                *hit_stop_frames -= 1;
+                return Ok(());
             }
+        }
 
-            player_pos.y = player_pos.y.min(crate::SCREEN_HEIGHT - 45.0).max(0.0);
-            player_pos.x = player_pos.x.min(crate::SCREEN_WIDTH - 45.0).max(0.0);
+        let key_bindings = &self.settings.key_bindings;
+        let input = sim::Input {
+            up: key_bindings.is_pressed(ctx, Action::Up),
+            down: key_bindings.is_pressed(ctx, Action::Down),
+            left: key_bindings.is_pressed(ctx, Action::Left),
+            right: key_bindings.is_pressed(ctx, Action::Right),
+            shoot: key_bindings.is_pressed(ctx, Action::Shoot),
+            focus: key_bindings.is_pressed(ctx, Action::Focus),
+            deflect: key_bindings.is_pressed(ctx, Action::Deflect) || self.deflect_pressed,
+        };
+        self.deflect_pressed = false;
+        let start = Instant::now();
+        sim::step(&mut self.world, &mut self.dispatcher, input);
+        self.world
+            .fetch::<SystemTimings>()
+            .record("update", start, start.elapsed());
+
+        // the high score is saved when the run ends
+        if !dead && self.world.fetch::<Dead>().0 {
+            let high_score = self.world.fetch::<Score>().high;
+            let difficulty = *self.world.fetch::<Difficulty>();
+            let mut save_data = SaveData::load();
+            if high_score > save_data.high_score(difficulty) {
+                save_data.high_scores.insert(difficulty, high_score);
+                save_data.save();
+            }
         }
 
-        self.dispatcher.dispatch_par(&self.world);
-        self.world.maintain();
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        graphics::clear(ctx, Color::new(0.0, 0.0, 0.0, 1.0));
+        let start = Instant::now();
+        self.screen.begin(ctx)?;
 
-        // this builder is used for all meshes, which is really just stars
-        let mut builder = MeshBuilder::new();
-        {
-            let positions = self.world.read_storage::<Position>();
-            let colorects = self.world.read_storage::<ColorRect>();
-            let sprites = self.world.read_storage::<Sprite>();
-            let stars = self.world.read_storage::<Star>();
-            let hp_storage = self.world.read_storage::<HP>();
-            let entities = self.world.entities();
-            let animated_sprite_storage = self.world.read_storage::<AnimatedSprite>();
-
-            // stars need to be drawn first so they stay in the background,
-            // in practice they're the only colorects so it doesn't matter
-            {
-                (&positions, &colorects, &stars)
-                    .join()
-                    .for_each(|(pos, colorect, _)| {
-                        if pos.0.y > 0.0 {
-                            draw_colorect(&mut builder, (*pos).into(), &colorect);
-                        }
-                    });
-
-                (&positions, &colorects, !&stars)
-                    .join()
-                    .for_each(|(pos, colorect, _)| {
-                        draw_colorect(&mut builder, (*pos).into(), &colorect);
-                    });
-            }
+        // everything in the world is offset by the camera, but the hud isn't
+        let camera_offset = self.world.fetch::<Camera>().offset;
+        graphics::push_transform(
+            ctx,
+            Some(
+                DrawParam::new()
+                    .dest(Point::new(camera_offset.x, camera_offset.y))
+                    .to_matrix(),
+            ),
+        );
+        graphics::apply_transformations(ctx)?;
 
-            // draw all sprites, as part of a spritesheet or not
-            (&positions, &sprites, &entities)
-                .join()
-                .for_each(|(pos, sprite, entity)| {
-                    // sprites with iframes remaining should be lower opacity
-                    let draw_color = if let Some(hp) = hp_storage.get(entity) {
-                        let opacity = (60 - hp.iframes as u32) as f32 / 60.0;
-                        Color::new(1.0, 1.0, 1.0, opacity.powi(5))
-                    } else {
-                        graphics::WHITE
-                    };
-
-                    match sprite {
-                        Sprite::Img(img) => {
-                            // a normal, sprite, in practice just the player
-                            graphics::draw(
-                                ctx,
-                                img,
-                                graphics::DrawParam::new()
-                                    .scale([3.0, 3.0])
-                                    .dest(pos.0)
-                                    .color(draw_color),
-                            )
-                            .expect("error drawing sprite");
-                        }
-                        Sprite::SpriteSheetInstance(spritesheet, index) => {
-                            // an instance of a spritesheet,
-                            // this includes bullets, enemies, etc.
-                            let mut spritesheet =
-                                spritesheet.lock().expect("error locking spritesheet");
-                            let frame_width = 1.0 / spritesheet.width as f32;
-                            let src_rect =
-                                Rect::new(frame_width * *index as f32, 0.0, frame_width, 1.0);
-                            spritesheet.batch.add(
-                                DrawParam::new()
-                                    .src(src_rect)
-                                    .scale([3.0, 3.0])
-                                    .dest(pos.0)
-                                    .color(draw_color),
-                            );
-                        }
-                    }
-                });
-
-            // animated sprites are drawn similarly to how spritesheet instances are
-            (&positions, &animated_sprite_storage)
-                .join()
-                .for_each(|(pos, animated_sprite)| {
-                    let frame_width = 1.0 / animated_sprite.spritesheet_width as f32;
-                    let src_rect = Rect::new(
-                        animated_sprite.current_frame as f32 * frame_width,
-                        0.0,
-                        frame_width,
-                        1.0,
-                    );
-                    graphics::draw(
-                        ctx,
-                        &animated_sprite.spritesheet,
-                        graphics::DrawParam::new()
-                            .src(src_rect)
-                            .scale([3.5, 3.5])
-                            .dest(pos.0),
-                    )
-                    .expect("error drawing animated sprite");
-                });
-
-            if cfg!(feature = "draw_hitboxes") {
-                let hitboxes = self.world.read_storage::<Hitbox>();
-                (&positions, &hitboxes).join().for_each(|(pos, hitbox)| {
-                    let rect = Rect::new(
-                        pos.0.x + hitbox.0.x,
-                        pos.0.y + hitbox.0.y,
-                        hitbox.1,
-                        hitbox.2,
-                    );
-                    builder.rectangle(DrawMode::stroke(2.5), rect, Color::new(1.0, 0.0, 0.0, 1.0));
-                });
-            }
-        }
+        let commands = crate::render::world_commands(&self.world);
+        crate::render::draw(ctx, &self.world, &commands)?;
 
-        {
-            // clear spritebatches
-            let spritesheets = self
-                .world
-                .get_mut::<SpriteSheets>()
-                .expect("error getting spritesheet");
-            spritesheets.0.values_mut().for_each(|spritesheet| {
-                let mut spritesheet = spritesheet.lock().expect("error locking spritesheet");
-                graphics::draw(ctx, &spritesheet.batch, graphics::DrawParam::new())
-                    .expect("error drawing spritebatch");
-                spritesheet.batch.clear();
-            });
-        }
+        graphics::pop_transform(ctx);
+        graphics::apply_transformations(ctx)?;
 
-        // draw meshbuilder
-        let mesh = builder.build(ctx)?;
-        graphics::draw(ctx, &mesh, DrawParam::new())?;
-
-        // draw info text
-        let heart_sprite = self
-            .world
-            .fetch::<Sprites>()
-            .0
-            .get("heart")
-            .expect("error getting heart sprite")
-            .clone();
-        graphics::draw(
-            ctx,
-            &heart_sprite,
-            graphics::DrawParam::new()
-                .dest([13.5, crate::SCREEN_HEIGHT - 130.0])
-                .scale(Vector::new(0.45, 0.45)),
-        )
-        .expect("error drawing heart sprite");
-        let text_mutex = &self.world.fetch::<HPText>().text;
-        let text = text_mutex.lock().expect("error locking hp text");
-        graphics::draw(
-            ctx,
-            &*text,
-            graphics::DrawParam::new().dest([50.0, crate::SCREEN_HEIGHT - 100.0]),
-        )
-        .expect("error drawing hp text");
+        // the hud is drawn straight to the window
+        let visible = self.screen.finish(ctx, self.settings.scanlines)?;
+
+        crate::hud::draw(ctx, &self.world, visible)?;
+        self.debug.draw(ctx, &self.world, visible)?;
 
         // draw dead text
         if self.world.fetch::<Dead>().0 {
@@ -436,95 +242,166 @@ impl EventHandler for GameState<'_, '_> {
             .expect("error drawing dead text");
         }
 
+        if let Some(ship_select) = &self.ship_select {
+            let font = self.world.fetch::<GameFont>().0;
+            ship_select.draw(
+                ctx,
+                font,
+                &self.world.fetch::<Ships>(),
+                &self.world.fetch::<Sprites>(),
+            )?;
+        }
+
+        if let Some(pause_menu) = &self.pause_menu {
+            let font = self.world.fetch::<GameFont>().0;
+            pause_menu.draw(ctx, font, &self.settings)?;
+        }
+
         graphics::present(ctx)?;
+
+        let timings = self.world.fetch::<SystemTimings>();
+        timings.record("draw", start, start.elapsed());
+        timings.end_frame();
         Ok(())
     }
 
-    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
-        // maintain aspect ratio regardless of window size
-        // if the screen is a vertical rectangle add space to the top and bottom,
-        // if it's horizontal add space to the left and right
-        use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
-        let aspect_ratio = height / width;
-        let initial_ratio = SCREEN_HEIGHT / SCREEN_WIDTH;
-
-        if initial_ratio > aspect_ratio {
-            // width is greater than usual
-            let new_width = SCREEN_WIDTH / aspect_ratio;
-            let excess_width = new_width - SCREEN_WIDTH;
-            ggez::graphics::set_screen_coordinates(
-                ctx,
-                graphics::Rect::new(-excess_width / 2.0, 0.0, new_width, SCREEN_HEIGHT),
-            )
-            .expect("error setting screen coordinates on resize");
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        if let Some(output) = &self.profile_output {
+            let profiler = self
+                .world
+                .fetch::<SystemTimings>()
+                .0
+                .lock()
+                .expect("error locking system timings")
+                .clone();
+            match profiler.export(output) {
+                Ok(()) => println!(
+                    "{}",
+                    crate::profiler::format_summaries(&profiler.summarize())
+                ),
+                Err(e) => log::warn!("{}", e),
+            }
+        }
+        false
+    }
+
+    // the music stops while the window is in the background, and the game pauses
+    fn focus_event(&mut self, _ctx: &mut Context, gained: bool) {
+        if gained {
+            self.mixer.resume_music();
         } else {
-            // height is greater than usual
-            let new_height = SCREEN_HEIGHT * aspect_ratio;
-            let excess_height = new_height - SCREEN_HEIGHT;
-            ggez::graphics::set_screen_coordinates(
-                ctx,
-                graphics::Rect::new(0.0, -excess_height / 2.0, SCREEN_WIDTH, new_height),
-            )
-            .expect("error setting screen coordinates on resize");
+            self.mixer.pause_music();
+            if self.pause_menu.is_none() {
+                self.set_paused(true);
+            }
         }
     }
 
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+        self.debug.text_input(character);
+    }
+
     fn key_down_event(
         &mut self,
-        _ctx: &mut Context,
+        ctx: &mut Context,
         keycode: KeyCode,
         _keymods: KeyMods,
         _repeat: bool,
     ) {
-        if keycode == KeyCode::Space && self.world.fetch::<Dead>().0 {
-            // respawn/restart game
-            let player_sprite = self
-                .world
-                .fetch::<Sprites>()
-                .0
-                .get("player")
-                .expect("error gettng player sprite")
-                .clone();
-            let player = new_player(player_sprite, 5);
-            let player = create_player(&mut self.world, player);
-            self.world.insert(PlayerEntity(player));
-
-            self.world.insert(Dead(false));
-            self.world.insert(CurrentWave(0));
-            self.world.fetch_mut::<HPText>().needs_redraw = true;
-
-            {
-                let entities = self.world.entities();
-                let enemies = self.world.read_storage::<Enemy>();
-                let bullets = self.world.read_storage::<Bullet>();
-                entities.join().for_each(|entity| {
-                    if enemies.get(entity).is_some() || bullets.get(entity).is_some() {
-                        entities
-                            .delete(entity)
-                            .expect("error deleting enemy or bullet");
-                    }
-                });
+        if let Some(pause_menu) = &mut self.pause_menu {
+            let previous_settings = self.settings.clone();
+            match pause_menu.key_down(keycode, &mut self.settings) {
+                MenuAction::Close => self.set_paused(false),
+                MenuAction::SettingsChanged => self.apply_settings(ctx, &previous_settings),
+                MenuAction::None => {}
+            }
+            return;
+        }
+
+        if let Some(ship_select) = &mut self.ship_select {
+            let ship = ship_select.key_down(keycode, &self.world.fetch::<Ships>());
+            if let Some(ship) = ship {
+                self.start_run(ship);
             }
-            self.world.maintain();
+            return;
         }
 
-        if keycode == KeyCode::LControl && !self.world.fetch::<Dead>().0 {
-            // deflection activator
-            let mut players = self.world.write_storage::<Player>();
-            let player_entity = self.world.fetch::<PlayerEntity>().0;
-            let player = players
-                .get_mut(player_entity)
-                .expect("error getting player entity");
-
-            if player.deflector_cooldown == 0 {
-                player.deflector_timer = player.deflector_frames;
-                player.deflector_cooldown = player.deflector_reload_frames;
+        if DebugTools::enabled() && self.debug.key_down(&mut self.world, keycode) {
+            return;
+        }
+
+        let key_bindings = &self.settings.key_bindings;
+        if key_bindings.is_bound(keycode, Action::Pause) {
+            self.set_paused(true);
+            return;
+        }
+
+        if key_bindings.is_bound(keycode, Action::Deflect) {
+            self.deflect_pressed = true;
+        }
+
+        if key_bindings.is_bound(keycode, Action::Shoot) && self.world.fetch::<Dead>().0 {
+            let ships = self.world.fetch::<Ships>();
+            if ships.0.len() > 1 {
+                self.ship_select = Some(ShipSelect::new(&ships, &self.settings.ship));
+            } else {
+                let ship = ships.get(&self.settings.ship);
+                drop(ships);
+                self.start_run(ship);
             }
         }
     }
 }
 
-fn draw_colorect(builder: &mut MeshBuilder, pos: Point, colorect: &ColorRect) {
-    let rect = Rect::new(pos.x, pos.y, colorect.w, colorect.h);
-    builder.rectangle(DrawMode::fill(), rect, colorect.color);
+impl GameState<'_, '_> {
+    pub fn set_profile_output(&mut self, output: PathBuf) {
+        self.profile_output = Some(output);
+    }
+
+    // closes the ship select and starts over with `ship`. a difficulty picked in the menu also
+    // starts with the next run
+    fn start_run(&mut self, ship: Ship) {
+        self.ship_select = None;
+        if ship.name != self.settings.ship {
+            self.settings.ship = ship.name.clone();
+            self.settings.save();
+        }
+        self.world.insert(ship);
+
+        let difficulty = self.settings.difficulty;
+        self.world.insert(difficulty);
+        self.world.fetch_mut::<Rank>().enabled = self.settings.rank;
+        self.world.fetch_mut::<Score>().high = SaveData::load().high_score(difficulty);
+        if self.world.fetch::<Dead>().0 {
+            sim::restart(&mut self.world);
+        } else {
+            // the first run, the player hasn't played as the ship from the settings yet
+            let starting_hp = sim::difficulty_stats(&self.world).starting_hp;
+            sim::start_run(&mut self.world, starting_hp);
+        }
+    }
+
+    // changes from the options menu take effect immediately, except for vsync
+    fn apply_settings(&mut self, ctx: &mut Context, previous_settings: &Settings) {
+        self.mixer.set_volumes(&self.settings);
+        self.world.fetch_mut::<Camera>().shake_enabled = self.settings.screen_shake;
+
+        // changing the window mode recreates the window surface, so only do it when needed
+        if self.settings.window_mode() != previous_settings.window_mode() {
+            self.settings.apply_window_mode(ctx);
+        }
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        if paused {
+            self.pause_menu = Some(PauseMenu::default());
+        } else {
+            self.pause_menu = None;
+            // settings are only saved once the menu is closed instead of on every change
+            self.settings.save();
+        }
+        self.mixer.set_ducked(paused);
+    }
 }
+
+// anything smaller than a pixel of the canvas would flicker in and out, so it's made bigger
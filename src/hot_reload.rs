@@ -0,0 +1,248 @@
+use ggez::audio::SoundData;
+use ggez::graphics::Image;
+use ggez::{Context, GameResult};
+
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use specs::prelude::*;
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+use crate::assets::{self, AssetManifest};
+use crate::atlas::Atlas;
+use crate::ecs::{components, resources};
+
+// data files are reloaded by a function instead of being swapped like assets
+pub type DataReloader = fn(&mut Context, &mut World) -> GameResult;
+
+// watches the resources directory and swaps assets in place while the game is running.
+// only meant for development, it's behind the hot_reload feature
+pub struct HotReloader {
+    _watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+    resources_dir: PathBuf,
+    data_files: Vec<(String, DataReloader)>,
+}
+
+impl HotReloader {
+    pub fn new(resources_dir: &Path) -> Option<Self> {
+        let resources_dir = resources_dir.canonicalize().ok()?;
+        let (tx, events) = channel();
+
+        let mut watcher = notify::watcher(tx, Duration::from_millis(200))
+            .map_err(|e| log::warn!("error creating file watcher: {}", e))
+            .ok()?;
+        watcher
+            .watch(&resources_dir, RecursiveMode::Recursive)
+            .map_err(|e| log::warn!("error watching {}: {}", resources_dir.display(), e))
+            .ok()?;
+
+        let mut hot_reloader = HotReloader {
+            _watcher: watcher,
+            events,
+            resources_dir,
+            data_files: Vec::new(),
+        };
+        hot_reloader.watch_data(assets::MANIFEST_PATH, reload_manifest);
+        hot_reloader.watch_data(assets::BALANCE_PATH, reload_balance);
+        Some(hot_reloader)
+    }
+
+    // `path` is a ggez filesystem path, like "/assets.ron"
+    pub fn watch_data(&mut self, path: &str, reloader: DataReloader) {
+        self.data_files.push((path.to_string(), reloader));
+    }
+
+    pub fn update(&mut self, ctx: &mut Context, world: &mut World) {
+        // editors tend to save a file in several steps, so collect everything first
+        let changed: BTreeSet<String> = self
+            .events
+            .try_iter()
+            .filter_map(|event| match event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Rename(_, path) => self.vfs_path(&path),
+                _ => None,
+            })
+            .collect();
+
+        changed.iter().for_each(|path| {
+            let data_file = self
+                .data_files
+                .iter()
+                .find(|(data_path, _)| data_path == path);
+            if let Some((_, reloader)) = data_file {
+                log::info!("reloading {}", path);
+                if let Err(e) = reloader(ctx, world) {
+                    log::warn!("error reloading {}: {}", path, e);
+                }
+                return;
+            }
+
+            match AssetManifest::load(ctx) {
+                Ok(manifest) => {
+                    let errors = reload_assets(ctx, &manifest, world, &|asset| asset == path);
+                    errors
+                        .iter()
+                        .for_each(|e| log::warn!("error reloading {}", e));
+                }
+                Err(e) => log::warn!("error loading asset manifest: {}", e),
+            }
+        });
+    }
+
+    fn vfs_path(&self, path: &Path) -> Option<String> {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let relative = path.strip_prefix(&self.resources_dir).ok()?;
+        let components: Vec<_> = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect();
+        Some(format!("/{}", components.join("/")))
+    }
+}
+
+// a changed manifest can point anywhere, so everything in it is reloaded
+fn reload_manifest(ctx: &mut Context, world: &mut World) -> GameResult {
+    let manifest = AssetManifest::load(ctx)?;
+    world.insert(assets::sound_configs(&manifest));
+    world.insert(manifest.music_tracks.clone());
+    world.insert(resources::Ships(manifest.ships.clone()));
+    let errors = reload_assets(ctx, &manifest, world, &|_| true);
+    errors
+        .iter()
+        .for_each(|e| log::warn!("error reloading {}", e));
+    Ok(())
+}
+
+// enemies that are already up keep their hp and reload, the rest applies straight away
+fn reload_balance(ctx: &mut Context, world: &mut World) -> GameResult {
+    world.insert(assets::load_balance(ctx)?);
+    Ok(())
+}
+
+// reloads every asset whose file matches `changed`. existing assets are changed in place so that
+// entities which already hold them pick up the new version, and anything that fails to load keeps
+// its old version instead of becoming a placeholder
+pub fn reload_assets(
+    ctx: &mut Context,
+    manifest: &AssetManifest,
+    world: &mut World,
+    changed: &dyn Fn(&str) -> bool,
+) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    {
+        let sprites = &mut world.fetch_mut::<resources::Sprites>().0;
+        manifest
+            .sprites
+            .iter()
+            .filter(|(_, path)| changed(path))
+            .for_each(|(key, path)| match Image::new(ctx, path) {
+                Ok(image) => {
+                    sprites.insert(key.clone(), image);
+                }
+                Err(e) => errors.push(format!("sprite {} ({}): {}", key, path, e)),
+            });
+    }
+
+    {
+        let spritesheets = &mut world.fetch_mut::<resources::SpriteSheets>().0;
+        manifest.spritesheets.iter().for_each(|(key, atlas_path)| {
+            let image_changed = match spritesheets.get(key) {
+                Some(spritesheet) => {
+                    let spritesheet = spritesheet.lock().expect("error locking spritesheet");
+                    changed(&spritesheet.atlas.image)
+                }
+                None => false,
+            };
+            if !changed(atlas_path) && !image_changed {
+                return;
+            }
+
+            let atlas = match Atlas::load(ctx, atlas_path) {
+                Ok(atlas) => atlas,
+                Err(e) => {
+                    errors.push(format!("spritesheet {} ({}): {}", key, atlas_path, e));
+                    return;
+                }
+            };
+            let image = match Image::new(ctx, &atlas.image) {
+                Ok(image) => image,
+                Err(e) => {
+                    errors.push(format!("spritesheet {} ({}): {}", key, atlas.image, e));
+                    return;
+                }
+            };
+
+            match spritesheets.get(key) {
+                Some(spritesheet) => {
+                    let mut spritesheet = spritesheet.lock().expect("error locking spritesheet");
+                    spritesheet.image_size = (image.width(), image.height());
+                    spritesheet.atlas = atlas;
+                    if let Some(batch) = &mut spritesheet.batch {
+                        batch.set_image(image);
+                    }
+                }
+                None => {
+                    let spritesheet = resources::SpriteSheet::new(image, atlas);
+                    spritesheets.insert(key.clone(), std::sync::Arc::new(spritesheet.into()));
+                }
+            }
+        });
+    }
+
+    {
+        let animated_sprites = &mut world.fetch_mut::<resources::AnimatedSprites>().0;
+        manifest
+            .animations
+            .iter()
+            .filter(|(_, animation)| changed(&animation.image))
+            .for_each(|(key, animation)| match Image::new(ctx, &animation.image) {
+                Ok(image) => {
+                    let animated_sprite = components::AnimatedSprite::new(
+                        key,
+                        Some(image),
+                        animation.frames,
+                        animation.sheet_width,
+                        !animation.looping,
+                    );
+                    animated_sprites.insert(key.clone(), animated_sprite);
+                }
+                Err(e) => errors.push(format!("animation {} ({}): {}", key, animation.image, e)),
+            });
+    }
+
+    // sounds are copied out of the map when they're queued, so they can just be replaced
+    {
+        let sounds = &mut world.fetch_mut::<resources::Sounds>().0;
+        reload_sounds(ctx, "sound", &manifest.sounds, sounds, changed, &mut errors);
+    }
+    {
+        let music = &mut world.fetch_mut::<resources::Music>().0;
+        reload_sounds(ctx, "music", &manifest.music, music, changed, &mut errors);
+    }
+
+    errors
+}
+
+fn reload_sounds(
+    ctx: &mut Context,
+    kind: &str,
+    entries: &std::collections::BTreeMap<String, String>,
+    sounds: &mut std::collections::HashMap<String, SoundData>,
+    changed: &dyn Fn(&str) -> bool,
+    errors: &mut Vec<String>,
+) {
+    entries
+        .iter()
+        .filter(|(_, path)| changed(path))
+        .for_each(|(key, path)| match SoundData::new(ctx, path) {
+            Ok(sound) => {
+                sounds.insert(key.clone(), sound);
+            }
+            Err(e) => errors.push(format!("{} {} ({}): {}", kind, key, path, e)),
+        });
+}
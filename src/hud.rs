@@ -0,0 +1,517 @@
+use ggez::{
+    graphics::{self, Color, Rect},
+    Context, GameResult,
+};
+use specs::prelude::*;
+
+use crate::ecs::components::*;
+use crate::ecs::resources::*;
+use crate::render::{self, DrawCommand, GgezMeasure, Measure, Texture};
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use std::f32::consts::PI;
+
+// side panels are only drawn when the letterbox is at least this wide, in game coordinates
+const MIN_PANEL_WIDTH: f32 = 180.0;
+const PADDING: f32 = 24.0;
+
+const READY_COLOR: Color = Color::new(0.4, 0.9, 1.0, 1.0);
+const CHARGING_COLOR: Color = Color::new(0.4, 0.5, 0.6, 1.0);
+const FAINT_COLOR: Color = Color::new(1.0, 1.0, 1.0, 0.25);
+
+// the area a widget is laid out in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Container {
+    Playfield,
+    LeftPanel,
+    RightPanel,
+}
+
+// which corner or edge of the container the widget's offset is measured from. the widget
+// grows away from its anchor, so right anchored widgets grow to the left and so on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Anchor {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum WidgetKind {
+    Label {
+        text: String,
+        size: f32,
+    },
+    // a sprite followed by "x count"
+    IconCounter {
+        icon: &'static str,
+        icon_scale: f32,
+        count: u32,
+        size: f32,
+    },
+    // fill is from 0 to 1
+    Bar {
+        fill: f32,
+        w: f32,
+        h: f32,
+    },
+    // a ring that fills clockwise from the top
+    Gauge {
+        fill: f32,
+        radius: f32,
+        label: Option<&'static str>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Widget {
+    pub container: Container,
+    pub anchor: Anchor,
+    // distance from the anchor, towards the inside of the container
+    pub offset: [f32; 2],
+    pub kind: WidgetKind,
+    pub color: Color,
+}
+
+impl Widget {
+    pub fn new(container: Container, anchor: Anchor, offset: [f32; 2], kind: WidgetKind) -> Self {
+        Widget {
+            container,
+            anchor,
+            offset,
+            kind,
+            color: graphics::WHITE,
+        }
+    }
+
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    fn size(&self, measure: &mut impl Measure) -> (f32, f32) {
+        match &self.kind {
+            WidgetKind::Label { text, size } => measure.text_size(text, *size),
+            WidgetKind::IconCounter {
+                icon,
+                icon_scale,
+                count,
+                size,
+            } => {
+                let (icon_w, icon_h) = icon_size(measure, icon, *icon_scale);
+                let (text_w, text_h) = measure.text_size(&format!("x {}", count), *size);
+                (icon_w + text_w, icon_h.max(text_h))
+            }
+            WidgetKind::Bar { w, h, .. } => (*w, *h),
+            WidgetKind::Gauge { radius, .. } => (radius * 2.0, radius * 2.0),
+        }
+    }
+
+    // the top left corner of the widget
+    fn position(&self, container: Rect, (w, h): (f32, f32)) -> [f32; 2] {
+        let [offset_x, offset_y] = self.offset;
+        let x = match self.anchor {
+            Anchor::TopLeft | Anchor::BottomLeft => container.x + offset_x,
+            Anchor::TopCenter => container.x + (container.w - w) / 2.0 + offset_x,
+            Anchor::TopRight | Anchor::BottomRight => container.right() - offset_x - w,
+        };
+        let y = match self.anchor {
+            Anchor::TopLeft | Anchor::TopCenter | Anchor::TopRight => container.y + offset_y,
+            _ => container.bottom() - offset_y - h,
+        };
+        [x, y]
+    }
+
+    fn commands(
+        &self,
+        measure: &mut impl Measure,
+        containers: &Containers,
+        commands: &mut Vec<DrawCommand>,
+    ) {
+        let size = self.size(measure);
+        let [x, y] = self.position(containers.get(self.container), size);
+
+        match &self.kind {
+            WidgetKind::Label { text, size } => commands.push(DrawCommand::Text {
+                text: text.clone(),
+                size: *size,
+                dest: Point::new(x, y),
+                color: self.color,
+            }),
+            WidgetKind::IconCounter {
+                icon,
+                icon_scale,
+                count,
+                size: text_scale,
+            } => {
+                let (icon_w, icon_h) = icon_size(measure, icon, *icon_scale);
+                commands.push(DrawCommand::Image {
+                    texture: Texture::Sprite(icon.to_string()),
+                    src: Rect::one(),
+                    dest: Point::new(x, y + (size.1 - icon_h) / 2.0),
+                    scale: Vector::new(*icon_scale, *icon_scale),
+                    color: graphics::WHITE,
+                });
+                let text = format!("x {}", count);
+                let text_h = measure.text_size(&text, *text_scale).1;
+                commands.push(DrawCommand::Text {
+                    text,
+                    size: *text_scale,
+                    dest: Point::new(x + icon_w, y + (size.1 - text_h) / 2.0),
+                    color: self.color,
+                });
+            }
+            WidgetKind::Bar { fill, w, h } => {
+                commands.push(DrawCommand::Rect {
+                    rect: Rect::new(x, y, *w, *h),
+                    stroke: Some(3.0),
+                    color: self.color,
+                });
+                commands.push(DrawCommand::Rect {
+                    rect: Rect::new(x + 4.0, y + 4.0, (w - 8.0) * fill.clamp(0.0, 1.0), h - 8.0),
+                    stroke: None,
+                    color: self.color,
+                });
+            }
+            WidgetKind::Gauge {
+                fill,
+                radius,
+                label,
+            } => {
+                let center = Point::new(x + radius, y + radius);
+                let thickness = radius / 3.0;
+                let ring_radius = radius - thickness / 2.0;
+
+                commands.push(DrawCommand::Ring {
+                    center,
+                    radius: ring_radius,
+                    width: thickness,
+                    color: FAINT_COLOR,
+                });
+                commands.push(DrawCommand::Line {
+                    points: arc_points(center, ring_radius, fill.clamp(0.0, 1.0)),
+                    width: thickness,
+                    color: self.color,
+                });
+
+                if let Some(label) = label {
+                    let size = radius * 0.6;
+                    let (text_w, text_h) = measure.text_size(label, size);
+                    commands.push(DrawCommand::Text {
+                        text: label.to_string(),
+                        size,
+                        dest: Point::new(center.x - text_w / 2.0, center.y - text_h / 2.0),
+                        color: self.color,
+                    });
+                }
+            }
+        }
+    }
+}
+
+// the rects of each container in game coordinates for the current window
+struct Containers {
+    playfield: Rect,
+    left_panel: Rect,
+    right_panel: Rect,
+}
+
+impl Containers {
+    fn new(visible: Rect) -> Self {
+        Containers {
+            playfield: Rect::new(0.0, 0.0, SCREEN_WIDTH, SCREEN_HEIGHT),
+            left_panel: Rect::new(visible.x, 0.0, -visible.x, SCREEN_HEIGHT),
+            right_panel: Rect::new(
+                SCREEN_WIDTH,
+                0.0,
+                visible.x + visible.w - SCREEN_WIDTH,
+                SCREEN_HEIGHT,
+            ),
+        }
+    }
+
+    fn get(&self, container: Container) -> Rect {
+        match container {
+            Container::Playfield => self.playfield,
+            Container::LeftPanel => self.left_panel,
+            Container::RightPanel => self.right_panel,
+        }
+    }
+}
+
+// everything the hud shows, read from the world once per frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct HudInfo {
+    score: Score,
+    grazes: u32,
+    hp: u32,
+    wave: u8,
+    // 1 when the deflector can be used again
+    deflector_charge: f32,
+    // between waves, how long until the next one spawns
+    next_wave: Option<f32>,
+}
+
+impl HudInfo {
+    fn new(world: &World) -> Self {
+        let dead = world.fetch::<Dead>().0;
+        let player_entity = world.fetch::<PlayerEntity>().0;
+        let (hp, deflector_charge) = if dead {
+            (0, 0.0)
+        } else {
+            let hp = world
+                .read_storage::<HP>()
+                .get(player_entity)
+                .map(|hp| hp.remaining)
+                .unwrap_or(0);
+            let charge = world
+                .read_storage::<Player>()
+                .get(player_entity)
+                .map(|player| {
+                    // charged deflectors cool down for longer, the gauge stays empty until then
+                    (1.0 - player.deflector_cooldown as f32
+                        / player.deflector.reload_frames.max(1) as f32)
+                        .max(0.0)
+                })
+                .unwrap_or(0.0);
+            (hp, charge)
+        };
+
+        let no_enemies = world.read_storage::<Enemy>().join().next().is_none();
+        let next_wave = if no_enemies && !dead {
+            let frames = f32::from(world.fetch::<FramesToNextWave>().0);
+            let total = f32::from(FramesToNextWave::default().0);
+            Some(1.0 - frames / total)
+        } else {
+            None
+        };
+
+        HudInfo {
+            score: *world.fetch::<Score>(),
+            grazes: world.fetch::<GrazeCount>().0,
+            hp,
+            wave: world.fetch::<CurrentWave>().0,
+            deflector_charge,
+            next_wave,
+        }
+    }
+
+    fn deflector_gauge(&self, radius: f32) -> (WidgetKind, Color) {
+        let ready = self.deflector_charge >= 1.0;
+        let kind = WidgetKind::Gauge {
+            fill: self.deflector_charge,
+            radius,
+            label: if ready { Some("D") } else { None },
+        };
+        (kind, if ready { READY_COLOR } else { CHARGING_COLOR })
+    }
+
+    // lives, wave and deflector over the playfield, for windows without room for the panels
+    fn playfield_widgets(&self) -> Vec<Widget> {
+        use Anchor::*;
+        use Container::Playfield;
+
+        let (gauge, gauge_color) = self.deflector_gauge(30.0);
+        let mut widgets = vec![
+            Widget::new(
+                Playfield,
+                BottomLeft,
+                [14.0, 70.0],
+                WidgetKind::IconCounter {
+                    icon: "heart",
+                    icon_scale: 0.45,
+                    count: self.hp,
+                    size: 48.0,
+                },
+            ),
+            Widget::new(
+                Playfield,
+                BottomLeft,
+                [14.0, 20.0],
+                WidgetKind::Label {
+                    text: format!("Wave: {}", self.wave),
+                    size: 48.0,
+                },
+            ),
+            Widget::new(Playfield, BottomRight, [20.0, 20.0], gauge).color(gauge_color),
+            Widget::new(
+                Playfield,
+                TopLeft,
+                [14.0, 14.0],
+                WidgetKind::Label {
+                    text: format!("{:08}", self.score.current),
+                    size: 32.0,
+                },
+            ),
+            Widget::new(
+                Playfield,
+                TopLeft,
+                [14.0, 50.0],
+                WidgetKind::Label {
+                    text: format!("GRAZE {}", self.grazes),
+                    size: 24.0,
+                },
+            ),
+            Widget::new(
+                Playfield,
+                TopRight,
+                [14.0, 14.0],
+                WidgetKind::Label {
+                    text: format!("HI {:08}", self.score.high),
+                    size: 32.0,
+                },
+            ),
+        ];
+
+        if let Some(fill) = self.next_wave {
+            widgets.push(Widget::new(
+                Playfield,
+                TopCenter,
+                [0.0, 14.0],
+                WidgetKind::Bar {
+                    fill,
+                    w: 200.0,
+                    h: 20.0,
+                },
+            ));
+        }
+
+        widgets
+    }
+
+    fn side_panel_widgets(&self, panel_width: f32) -> Vec<Widget> {
+        use Anchor::*;
+        use Container::*;
+
+        let label = |container, y, text: &str| {
+            Widget::new(
+                container,
+                TopLeft,
+                [PADDING, y],
+                WidgetKind::Label {
+                    text: text.to_string(),
+                    size: 28.0,
+                },
+            )
+        };
+        let value = |container, y, text: String| {
+            Widget::new(
+                container,
+                TopLeft,
+                [PADDING, y],
+                WidgetKind::Label { text, size: 44.0 },
+            )
+        };
+
+        let gauge_radius = ((panel_width - PADDING * 2.0) / 2.0).min(48.0);
+        let (gauge, gauge_color) = self.deflector_gauge(gauge_radius);
+        let mut widgets = vec![
+            label(LeftPanel, 60.0, "SCORE"),
+            value(LeftPanel, 100.0, format!("{:08}", self.score.current)),
+            label(LeftPanel, 200.0, "HI-SCORE"),
+            value(LeftPanel, 240.0, format!("{:08}", self.score.high)),
+            label(LeftPanel, 340.0, "GRAZE"),
+            value(LeftPanel, 380.0, self.grazes.to_string()),
+            label(RightPanel, 60.0, "WAVE"),
+            value(RightPanel, 100.0, self.wave.to_string()),
+            label(RightPanel, 200.0, "HP"),
+            Widget::new(
+                RightPanel,
+                TopLeft,
+                [PADDING, 236.0],
+                WidgetKind::IconCounter {
+                    icon: "heart",
+                    icon_scale: 0.45,
+                    count: self.hp,
+                    size: 44.0,
+                },
+            ),
+            label(RightPanel, 340.0, "DEFLECTOR"),
+            Widget::new(RightPanel, TopLeft, [PADDING, 380.0], gauge).color(gauge_color),
+        ];
+
+        if let Some(fill) = self.next_wave {
+            widgets.push(label(RightPanel, 520.0, "NEXT WAVE"));
+            widgets.push(Widget::new(
+                RightPanel,
+                TopLeft,
+                [PADDING, 560.0],
+                WidgetKind::Bar {
+                    fill,
+                    w: (panel_width - PADDING * 2.0).min(240.0),
+                    h: 24.0,
+                },
+            ));
+        }
+
+        widgets
+    }
+}
+
+// `visible` is the part of the window in game coordinates, anything left of 0 or right of
+// SCREEN_WIDTH is letterbox that can be used for the panels
+pub fn commands(world: &World, visible: Rect, measure: &mut impl Measure) -> Vec<DrawCommand> {
+    let containers = Containers::new(visible);
+    let info = HudInfo::new(world);
+    let side_panels = containers.left_panel.w >= MIN_PANEL_WIDTH;
+
+    let mut commands = Vec::new();
+    let widgets = if side_panels {
+        panel_backgrounds(&containers, &mut commands);
+        info.side_panel_widgets(containers.right_panel.w)
+    } else {
+        info.playfield_widgets()
+    };
+
+    widgets
+        .iter()
+        .for_each(|widget| widget.commands(measure, &containers, &mut commands));
+    commands
+}
+
+pub fn draw(ctx: &mut Context, world: &World, visible: Rect) -> GameResult {
+    let commands = commands(world, visible, &mut GgezMeasure { ctx, world });
+    render::draw(ctx, world, &commands)
+}
+
+fn panel_backgrounds(containers: &Containers, commands: &mut Vec<DrawCommand>) {
+    let border_color = Color::new(0.3, 0.3, 0.45, 1.0);
+    [containers.left_panel, containers.right_panel]
+        .iter()
+        .for_each(|panel| {
+            commands.push(DrawCommand::Rect {
+                rect: *panel,
+                stroke: None,
+                color: Color::new(0.06, 0.06, 0.1, 1.0),
+            });
+        });
+    // a border between the playfield and the panels
+    [-3.0, SCREEN_WIDTH].iter().for_each(|x| {
+        commands.push(DrawCommand::Rect {
+            rect: Rect::new(*x, 0.0, 3.0, SCREEN_HEIGHT),
+            stroke: None,
+            color: border_color,
+        });
+    });
+}
+
+fn icon_size(measure: &mut impl Measure, icon: &str, scale: f32) -> (f32, f32) {
+    let (w, h) = measure.texture_size(&Texture::Sprite(icon.to_string()));
+    (w * scale, h * scale)
+}
+
+// points along a circle starting at the top and going clockwise for `fill` of a full turn
+fn arc_points(center: Point, radius: f32, fill: f32) -> Vec<Point> {
+    let segments = (fill * 48.0).ceil() as usize;
+    (0..=segments)
+        .map(|i| {
+            let angle = -PI / 2.0 + 2.0 * PI * fill * i as f32 / segments.max(1) as f32;
+            Point::new(
+                center.x + radius * angle.cos(),
+                center.y + radius * angle.sin(),
+            )
+        })
+        .collect()
+}
@@ -1,37 +1,71 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
-use ggez::{event, graphics::spritebatch::SpriteBatch, GameResult};
-use specs::prelude::*;
+use ggez::{event, GameResult};
 
 use log;
 use simple_logger;
 
-use std::collections::HashMap;
-
-use std::sync::{Arc, Mutex};
+use std::sync::Mutex;
 
+mod assets;
+mod atlas;
+mod audio;
+mod bot;
+mod cli;
+mod debug;
 mod game_state;
+#[cfg(feature = "hot_reload")]
+mod hot_reload;
+mod hud;
+
+mod menu;
+mod profiler;
+mod render;
+mod save;
+mod screen;
+mod settings;
+mod sim;
+mod waves;
 
 mod ecs;
 
-use ecs::{components, resources, systems};
+use ecs::resources;
 
 const SCREEN_WIDTH: f32 = 1024.0 * 0.75;
 const SCREEN_HEIGHT: f32 = 1024.0 * 0.75;
 
-const VOLUME_MULTIPLIER: f32 = 0.2;
+// the resources directory in the source tree, watched by the hot_reload feature
+const RESOURCES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/resources");
+
+// sprites are drawn at this many times their size, SCREEN_WIDTH / PIXEL_SCALE is the
+// resolution the game is rendered at
+const PIXEL_SCALE: f32 = 3.0;
+
+// used for the user config directory, which is where settings are saved
+const GAME_ID: &str = "ssshmup";
+const AUTHOR: &str = "Mikail Khan";
 
 fn main() -> GameResult {
     simple_logger::init_with_level(log::Level::Warn).expect("error initializing logger");
 
-    let (ctx, event_loop) = &mut ggez::ContextBuilder::new("ssshmup", "Mikail Khan")
-        .window_setup(ggez::conf::WindowSetup::default().title("ssshmup"))
-        .window_mode(
-            ggez::conf::WindowMode::default()
-                .dimensions(SCREEN_WIDTH, SCREEN_HEIGHT)
-                .resizable(true),
-        )
-        .build()
-        .expect("error building context");
+    // `--profile <output>` plays the game and writes the system timings to <output>.json and
+    // <output>.csv when it closes. anything else is a command line tool, which runs without a window
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let profile_output = match args.as_slice() {
+        [flag, output] if flag == "--profile" => Some(std::path::PathBuf::from(output)),
+        [command, args @ ..] => return cli::run(command, args),
+        [] => None,
+    };
+
+    // the window is set up from the saved settings
+    let settings = settings::Settings::load();
+    let mut context_builder = ggez::ContextBuilder::new(GAME_ID, AUTHOR)
+        .window_setup(settings.window_setup())
+        .window_mode(settings.window_mode());
+    // when hot reloading, assets are loaded from the source tree so that edits show up
+    if cfg!(feature = "hot_reload") {
+        context_builder = context_builder.add_resource_path(RESOURCES_DIR);
+    }
+    let (ctx, event_loop) = &mut context_builder.build().expect("error building context");
 
     // for pixel art to work
     ggez::graphics::set_default_filter(ctx, ggez::graphics::FilterMode::Nearest);
@@ -43,107 +77,44 @@ fn main() -> GameResult {
     )
     .expect("error setting default screen coordinates");
 
-    // initialize specs world and register components
-    // not all components have to be registered here if they're used in systems
-    // but it doesn't hurt
-    let mut world = World::new();
-    world.register::<components::Position>();
-    world.register::<components::Player>();
-    world.register::<components::Velocity>();
-    world.register::<components::ColorRect>();
-    world.register::<components::HP>();
-    world.register::<components::Enemy>();
-    world.register::<components::Bullet>();
-    world.register::<components::Sprite>();
-    world.register::<components::AnimatedSprite>();
-    world.register::<components::Hitbox>();
-
-    // conttrols star generation
-    world.insert(resources::StarInfo {
-        num_stars: 60,
-        size: 2.25,
-        size_variance: 1.5,
-        vel: 5.0,
-        vel_variance: 2.0,
-    });
+    let mut world = sim::create_world();
 
-    // load sprites and put them in the specs world
-    let mut sprites = HashMap::new();
-    let player_sprite =
-        ggez::graphics::Image::new(ctx, "/player.png").expect("error loading player sprite");
-    let player_deflector_sprite = ggez::graphics::Image::new(ctx, "/player_deflector.png")
-        .expect("error loading player deflector sprite");
-    let player_cooldown_sprite = ggez::graphics::Image::new(ctx, "/player_cooldown.png")
-        .expect("error loading player cooldown sprite");
-    sprites.insert("player".to_string(), player_sprite.clone());
-    sprites.insert("player_deflector".to_string(), player_deflector_sprite);
-    sprites.insert("player_cooldown".to_string(), player_cooldown_sprite);
-
-    let heart_sprite =
-        ggez::graphics::Image::new(ctx, "/heart.png").expect("error loading heart sprite");
-    sprites.insert("heart".to_string(), heart_sprite);
-
-    // animated sprites and spritesheets are loaded separately
-    let mut animated_sprites = HashMap::new();
-    let mut spritesheets = HashMap::new();
+    // load every sprite, sound and font listed in the asset manifest,
+    // anything missing is replaced with a placeholder
     {
-        use ggez::graphics::Image;
-        let bullet_spritesheet =
-            Image::new(ctx, "/bullet_sheet.png").expect("error loading bullet spritesheet");
-        let bullet_spritebatch = SpriteBatch::new(bullet_spritesheet);
-        spritesheets.insert(
-            "bullets".to_string(),
-            Arc::new(Mutex::new(resources::SpriteSheet {
-                width: 8, // spritesheet width has to be a power of two because of floating point rounding
-                batch: bullet_spritebatch,
-            })),
-        );
-
-        let enemy_spritesheet =
-            Image::new(ctx, "/enemy_sheet.png").expect("error loading enemy spritesheet");
-        let enemy_spritebatch = SpriteBatch::new(enemy_spritesheet);
-        spritesheets.insert(
-            "enemies".to_string(),
-            Arc::new(Mutex::new(resources::SpriteSheet {
-                width: 8,
-                batch: enemy_spritebatch,
-            })),
-        );
-
-        let explosion_img = Image::new(ctx, "/boom.png").expect("error loading explosion sprite");
-        animated_sprites.insert(
-            "explosion".to_string(),
-            components::AnimatedSprite::new(explosion_img, 12, 16, true),
-        );
+        let manifest = assets::AssetManifest::load(ctx).unwrap_or_else(|e| {
+            log::error!("error loading asset manifest, using placeholders: {}", e);
+            assets::AssetManifest::default()
+        });
+        let errors = assets::load(ctx, &manifest, &mut world);
+        if !errors.is_empty() {
+            log::error!(
+                "{} assets failed to load:\n{}",
+                errors.len(),
+                errors.join("\n")
+            );
+        }
     }
-    world.insert(resources::Sprites(sprites));
-    world.insert(resources::AnimatedSprites(animated_sprites));
-    world.insert(resources::SpriteSheets(spritesheets));
-
-    // initialize player entity
-    let player = components::new_player(player_sprite, 6);
-    let player = components::create_player(&mut world, player);
-    world.insert(components::PlayerEntity(player));
-
-    // default global values
-    world.insert(resources::LastUpdate(std::time::Duration::new(0, 0)));
-    world.insert(resources::CurrentWave(0));
-    world.insert(resources::QueuedEnemies(Vec::new()));
-    world.insert(resources::FramesToNextWave(30));
-    world.insert(resources::Dead(false));
+    let ship = world.fetch::<resources::Ships>().get(&settings.ship);
+    world.insert(ship);
+    world.insert(settings.difficulty);
+    world.insert(resources::Rank::new(settings.rank));
+    let starting_hp = sim::difficulty_stats(&world).starting_hp;
+    sim::spawn_player(&mut world, starting_hp);
+
+    world.insert(resources::Score {
+        current: 0,
+        high: save::SaveData::load().high_score(settings.difficulty),
+    });
+    world.insert(resources::Camera {
+        shake_enabled: settings.screen_shake,
+        ..resources::Camera::default()
+    });
 
     {
         // initialize text stuff
-        use ggez::graphics::{Font, Scale, Text};
-        let font = Font::new(ctx, "/fonts/Xolonium-Regular.ttf").expect("error loading font");
-        let mut text = Text::new(format!("    x {}\nWave: {}", 5, 0));
-        text.set_font(font, Scale::uniform(48.0));
-        world.insert(resources::HPText {
-            needs_redraw: false,
-            text: Mutex::new(text),
-        });
-        world.insert(resources::GameFont(font));
-
+        use ggez::graphics::{Scale, Text};
+        let font = world.fetch::<resources::GameFont>().0;
         let mut dead_text1 = Text::new("You Died!");
         dead_text1.set_font(font, Scale::uniform(96.0));
 
@@ -152,75 +123,16 @@ fn main() -> GameResult {
         world.insert(resources::DeadText(Mutex::new([dead_text1, dead_text2])));
     }
 
-    {
-        // load sound effects and put them in specs world
-        use ggez::audio::SoundData;
-
-        let mut sounds = HashMap::new();
-        {
-            use ggez::audio::{SoundSource, Source};
-            let bg_music_source = Source::new(ctx, "/bgmusic.ogg");
-            if let Ok(mut bg_music_source) = bg_music_source {
-                bg_music_source.set_repeat(true);
-                bg_music_source.set_volume(0.2 * VOLUME_MULTIPLIER);
-                if bg_music_source.play_detached().is_err() {
-                    log::warn!("error playing background music");
-                }
-            } else {
-                log::warn!("error loading background music");
-            }
-        }
-        sounds.insert(
-            "shoot".to_string(),
-            SoundData::new(ctx, "/shoot2.ogg").expect("error loading shoot2.ogg"),
-        );
-        sounds.insert(
-            "boom".to_string(),
-            SoundData::new(ctx, "/boom.ogg").expect("error loading boom.ogg"),
-        );
-        sounds.insert(
-            "dead".to_string(),
-            SoundData::new(ctx, "/dead.ogg").expect("error loading dead.ogg"),
-        );
-        sounds.insert(
-            "deflect".to_string(),
-            SoundData::new(ctx, "/deflect.ogg").expect("error loading deflect.ogg"),
-        );
-        world.insert(resources::Sounds(sounds));
-        world.insert(resources::QueuedSounds(Vec::new()));
-    }
+    let mixer = audio::Mixer::new(&settings);
 
-    let mut dispatcher = DispatcherBuilder::new()
-        .with(systems::EnemyMoveSys, "enemy_move_sys", &[])
-        .with(systems::BulletTrackingSys, "tracking_bullet_sys", &[])
-        .with(systems::BounceBulletSys, "bouncing_bullet_sys", &[])
-        .with(systems::IntegrateSys, "integrate_system", &[])
-        .with(systems::StarMoveSys, "star_system", &[])
-        .with(systems::ReloadTimerSys, "reload_timer_sys", &[])
-        .with(systems::DeflectorSys, "deflector_timer_sys", &[])
-        .with(systems::EnemyShootSys, "enemy_shoot_sys", &[])
-        .with(systems::AnimationSys, "animation_sys", &[])
-        .with(
-            systems::BulletCollSys,
-            "bullet_coll_sys",
-            &["integrate_system", "bouncing_bullet_sys"],
-        )
-        .with(
-            systems::PlayerCollSys,
-            "player_coll_sys",
-            &["integrate_system"],
-        )
-        .with(
-            systems::HPKillSys,
-            "hp_kill_sys",
-            &["bullet_coll_sys", "player_coll_sys"],
-        )
-        .with(systems::IFrameSys, "iframe_sys", &["hp_kill_sys"])
-        .build();
+    let mut dispatcher = sim::create_dispatcher();
 
     dispatcher.setup(&mut world);
 
-    let mut game_state = game_state::GameState::new(world, dispatcher);
+    let mut game_state = game_state::GameState::new(ctx, world, dispatcher, mixer, settings)?;
+    if let Some(output) = profile_output {
+        game_state.set_profile_output(output);
+    }
 
     event::run(ctx, event_loop, &mut game_state)
 }
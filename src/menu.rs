@@ -0,0 +1,453 @@
+use ggez::{
+    graphics::{self, Color, DrawMode, DrawParam, Font, Mesh, Rect, Scale, Text},
+    input::keyboard::KeyCode,
+    Context, GameResult,
+};
+
+use crate::ecs::resources::{Ship, Ships, Sprites, DIFFICULTIES};
+use crate::settings::{Action, Settings, WindowType, ACTIONS};
+
+// window sizes that can be picked in the options menu
+const WINDOW_SIZES: [(f32, f32); 5] = [
+    (768.0, 768.0),
+    (1024.0, 1024.0),
+    (1024.0, 768.0),
+    (1280.0, 720.0),
+    (1920.0, 1080.0),
+];
+
+const WINDOW_TYPES: [WindowType; 3] = [
+    WindowType::Windowed,
+    WindowType::Fullscreen,
+    WindowType::Borderless,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MenuPage {
+    Pause,
+    Options,
+    Controls,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MenuItem {
+    Resume,
+    Options,
+    Difficulty,
+    Rank,
+    WindowSize,
+    WindowType,
+    Vsync,
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    ScreenShake,
+    Scanlines,
+    Controls,
+    Binding(Action),
+    Back,
+}
+
+impl MenuPage {
+    fn title(self) -> &'static str {
+        match self {
+            MenuPage::Pause => "Paused",
+            MenuPage::Options => "Options",
+            MenuPage::Controls => "Controls",
+        }
+    }
+
+    fn items(self) -> Vec<MenuItem> {
+        match self {
+            MenuPage::Pause => vec![MenuItem::Resume, MenuItem::Options],
+            MenuPage::Options => vec![
+                MenuItem::Difficulty,
+                MenuItem::Rank,
+                MenuItem::WindowSize,
+                MenuItem::WindowType,
+                MenuItem::Vsync,
+                MenuItem::MasterVolume,
+                MenuItem::MusicVolume,
+                MenuItem::SfxVolume,
+                MenuItem::ScreenShake,
+                MenuItem::Scanlines,
+                MenuItem::Controls,
+                MenuItem::Back,
+            ],
+            MenuPage::Controls => ACTIONS
+                .iter()
+                .map(|action| MenuItem::Binding(*action))
+                .chain(std::iter::once(MenuItem::Back))
+                .collect(),
+        }
+    }
+
+    fn parent(self) -> Option<MenuPage> {
+        match self {
+            MenuPage::Pause => None,
+            MenuPage::Options => Some(MenuPage::Pause),
+            MenuPage::Controls => Some(MenuPage::Options),
+        }
+    }
+}
+
+// what the game state should do after a key press in the menu
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MenuAction {
+    None,
+    Close,
+    SettingsChanged,
+}
+
+// shown while the game is paused, up/down picks an item, left/right changes it
+// and enter opens it. menu keys can't be rebound so that the menu always works
+#[derive(Clone, Debug)]
+pub struct PauseMenu {
+    pub page: MenuPage,
+    pub selected: usize,
+    // the next key pressed is bound to this action
+    pub rebinding: Option<Action>,
+}
+
+impl Default for PauseMenu {
+    fn default() -> Self {
+        PauseMenu {
+            page: MenuPage::Pause,
+            selected: 0,
+            rebinding: None,
+        }
+    }
+}
+
+impl PauseMenu {
+    fn open(&mut self, page: MenuPage) {
+        self.page = page;
+        self.selected = 0;
+    }
+
+    pub fn key_down(&mut self, keycode: KeyCode, settings: &mut Settings) -> MenuAction {
+        if let Some(action) = self.rebinding.take() {
+            if keycode == KeyCode::Escape {
+                return MenuAction::None;
+            }
+            settings.key_bindings.rebind(action, keycode);
+            return MenuAction::SettingsChanged;
+        }
+
+        let items = self.page.items();
+        let change = match keycode {
+            KeyCode::Escape => {
+                return match self.page.parent() {
+                    Some(parent) => {
+                        self.open(parent);
+                        MenuAction::None
+                    }
+                    None => MenuAction::Close,
+                };
+            }
+            KeyCode::Up | KeyCode::W => {
+                self.selected = (self.selected + items.len() - 1) % items.len();
+                return MenuAction::None;
+            }
+            KeyCode::Down | KeyCode::S => {
+                self.selected = (self.selected + 1) % items.len();
+                return MenuAction::None;
+            }
+            KeyCode::Left | KeyCode::A => -1,
+            KeyCode::Right | KeyCode::D => 1,
+            KeyCode::Return | KeyCode::Space => 0,
+            _ => return MenuAction::None,
+        };
+
+        match items[self.selected] {
+            MenuItem::Resume if change == 0 => MenuAction::Close,
+            MenuItem::Options if change == 0 => {
+                self.open(MenuPage::Options);
+                MenuAction::None
+            }
+            MenuItem::Controls if change == 0 => {
+                self.open(MenuPage::Controls);
+                MenuAction::None
+            }
+            MenuItem::Back if change == 0 => {
+                if let Some(parent) = self.page.parent() {
+                    self.open(parent);
+                }
+                MenuAction::None
+            }
+            MenuItem::Binding(action) if change == 0 => {
+                self.rebinding = Some(action);
+                MenuAction::None
+            }
+            MenuItem::Difficulty if change != 0 => {
+                let current = DIFFICULTIES
+                    .iter()
+                    .position(|difficulty| *difficulty == settings.difficulty)
+                    .unwrap_or(0);
+                settings.difficulty = DIFFICULTIES[cycle(current, change, DIFFICULTIES.len())];
+                MenuAction::SettingsChanged
+            }
+            MenuItem::WindowSize => {
+                let current = WINDOW_SIZES
+                    .iter()
+                    .position(|size| *size == settings.window_size)
+                    .unwrap_or(0);
+                settings.window_size = WINDOW_SIZES[cycle(current, change, WINDOW_SIZES.len())];
+                MenuAction::SettingsChanged
+            }
+            MenuItem::WindowType => {
+                let current = WINDOW_TYPES
+                    .iter()
+                    .position(|ty| *ty == settings.window_type)
+                    .unwrap_or(0);
+                settings.window_type = WINDOW_TYPES[cycle(current, change, WINDOW_TYPES.len())];
+                MenuAction::SettingsChanged
+            }
+            MenuItem::Rank => {
+                settings.rank = !settings.rank;
+                MenuAction::SettingsChanged
+            }
+            MenuItem::Vsync => {
+                settings.vsync = !settings.vsync;
+                MenuAction::SettingsChanged
+            }
+            MenuItem::ScreenShake => {
+                settings.screen_shake = !settings.screen_shake;
+                MenuAction::SettingsChanged
+            }
+            MenuItem::Scanlines => {
+                settings.scanlines = !settings.scanlines;
+                MenuAction::SettingsChanged
+            }
+            MenuItem::MasterVolume | MenuItem::MusicVolume | MenuItem::SfxVolume if change != 0 => {
+                let volume = match items[self.selected] {
+                    MenuItem::MasterVolume => &mut settings.master_volume,
+                    MenuItem::MusicVolume => &mut settings.music_volume,
+                    _ => &mut settings.sfx_volume,
+                };
+                // rounded so that repeated steps don't drift
+                *volume = ((*volume * 10.0).round() + change as f32).clamp(0.0, 10.0) / 10.0;
+                MenuAction::SettingsChanged
+            }
+            _ => MenuAction::None,
+        }
+    }
+
+    fn label(&self, item: MenuItem, settings: &Settings) -> String {
+        let on_off = |on: bool| if on { "On" } else { "Off" };
+        match item {
+            MenuItem::Resume => "Resume".to_string(),
+            MenuItem::Options => "Options".to_string(),
+            MenuItem::Controls => "Controls".to_string(),
+            MenuItem::Back => "Back".to_string(),
+            MenuItem::WindowSize => {
+                let (width, height) = settings.window_size;
+                format!("Window size: < {}x{} >", width, height)
+            }
+            MenuItem::Difficulty => {
+                format!("Difficulty: < {:?} > (next run)", settings.difficulty)
+            }
+            MenuItem::Rank => format!("Rank: {} (next run)", on_off(settings.rank)),
+            MenuItem::WindowType => format!("Window: < {:?} >", settings.window_type),
+            MenuItem::Vsync => format!("VSync: {} (restart)", on_off(settings.vsync)),
+            MenuItem::MasterVolume => volume_label("Volume", settings.master_volume),
+            MenuItem::MusicVolume => volume_label("Music", settings.music_volume),
+            MenuItem::SfxVolume => volume_label("Effects", settings.sfx_volume),
+            MenuItem::ScreenShake => format!("Screen shake: {}", on_off(settings.screen_shake)),
+            MenuItem::Scanlines => format!("Scanlines: {}", on_off(settings.scanlines)),
+            MenuItem::Binding(action) if self.rebinding == Some(action) => {
+                format!("{:?}: press a key", action)
+            }
+            MenuItem::Binding(action) => {
+                let keys: Vec<String> = settings
+                    .key_bindings
+                    .keys(action)
+                    .iter()
+                    .map(|key| format!("{:?}", key))
+                    .collect();
+                format!("{:?}: {}", action, keys.join(", "))
+            }
+        }
+    }
+
+    pub fn draw(&self, ctx: &mut Context, font: Font, settings: &Settings) -> GameResult {
+        use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+        // darken the game behind the menu
+        let screen = Rect::new(0.0, 0.0, SCREEN_WIDTH, SCREEN_HEIGHT);
+        let shade = Mesh::new_rectangle(
+            ctx,
+            DrawMode::fill(),
+            screen,
+            Color::new(0.0, 0.0, 0.0, 0.6),
+        )?;
+        graphics::draw(ctx, &shade, DrawParam::new())?;
+
+        let mut title = Text::new(self.page.title());
+        title.set_font(font, Scale::uniform(96.0));
+        graphics::draw(
+            ctx,
+            &title,
+            DrawParam::new().dest([SCREEN_WIDTH / 8.0, SCREEN_HEIGHT / 8.0]),
+        )?;
+
+        self.page
+            .items()
+            .iter()
+            .enumerate()
+            .try_for_each(|(i, item)| {
+                let label = self.label(*item, settings);
+                let (label, color) = if i == self.selected {
+                    (format!("> {}", label), graphics::WHITE)
+                } else {
+                    (format!("  {}", label), Color::new(1.0, 1.0, 1.0, 0.6))
+                };
+
+                let mut text = Text::new(label);
+                text.set_font(font, Scale::uniform(32.0));
+                graphics::draw(
+                    ctx,
+                    &text,
+                    DrawParam::new()
+                        .dest([SCREEN_WIDTH / 8.0, SCREEN_HEIGHT / 3.6 + i as f32 * 44.0])
+                        .color(color),
+                )
+            })
+    }
+}
+
+// shown before the first run and after every death. left/right changes the ship and enter or
+// space starts the run with it
+#[derive(Clone, Debug, Default)]
+pub struct ShipSelect {
+    pub selected: usize,
+}
+
+impl ShipSelect {
+    // starts on the ship called `current`
+    pub fn new(ships: &Ships, current: &str) -> Self {
+        ShipSelect {
+            selected: ships
+                .0
+                .iter()
+                .position(|ship| ship.name == current)
+                .unwrap_or(0),
+        }
+    }
+
+    fn ship(&self, ships: &Ships) -> Ship {
+        ships.0.get(self.selected).cloned().unwrap_or_default()
+    }
+
+    // the ship once one is picked
+    pub fn key_down(&mut self, keycode: KeyCode, ships: &Ships) -> Option<Ship> {
+        let len = ships.0.len().max(1);
+        match keycode {
+            KeyCode::Left | KeyCode::A => self.selected = cycle(self.selected, -1, len),
+            KeyCode::Right | KeyCode::D => self.selected = cycle(self.selected, 1, len),
+            KeyCode::Return | KeyCode::Space => return Some(self.ship(ships)),
+            _ => {}
+        }
+        None
+    }
+
+    pub fn draw(
+        &self,
+        ctx: &mut Context,
+        font: Font,
+        ships: &Ships,
+        sprites: &Sprites,
+    ) -> GameResult {
+        use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+        let screen = Rect::new(0.0, 0.0, SCREEN_WIDTH, SCREEN_HEIGHT);
+        let shade = Mesh::new_rectangle(
+            ctx,
+            DrawMode::fill(),
+            screen,
+            Color::new(0.0, 0.0, 0.0, 0.8),
+        )?;
+        graphics::draw(ctx, &shade, DrawParam::new())?;
+
+        let mut draw_text = |text: String, size: f32, y: f32, color: Color| {
+            let mut text = Text::new(text);
+            text.set_font(font, Scale::uniform(size));
+            graphics::draw(
+                ctx,
+                &text,
+                DrawParam::new().dest([SCREEN_WIDTH / 8.0, y]).color(color),
+            )
+        };
+        let faded = Color::new(1.0, 1.0, 1.0, 0.6);
+
+        draw_text(
+            "Pick a ship".to_string(),
+            96.0,
+            SCREEN_HEIGHT / 8.0,
+            graphics::WHITE,
+        )?;
+
+        let ship = self.ship(ships);
+        let deflector = ship.deflector;
+        let lines = [
+            (format!("< {} >", ship.name), 48.0, graphics::WHITE),
+            (ship.description.clone(), 28.0, faded),
+            (
+                format!(
+                    "Speed {:.0}%, HP {:+}, {:?} shot",
+                    ship.speed * 100.0,
+                    ship.extra_hp,
+                    ship.fire_pattern
+                ),
+                28.0,
+                faded,
+            ),
+            (
+                format!(
+                    "Deflector charges to level {}, {:.1}s cooldown",
+                    deflector.max_level,
+                    deflector.reload_frames as f32 / 60.0
+                ),
+                28.0,
+                faded,
+            ),
+            ("Enter to start".to_string(), 28.0, graphics::WHITE),
+        ];
+        lines
+            .iter()
+            .enumerate()
+            .try_for_each(|(i, (text, size, color))| {
+                draw_text(
+                    text.clone(),
+                    *size,
+                    SCREEN_HEIGHT / 2.0 + i as f32 * 48.0,
+                    *color,
+                )
+            })?;
+
+        // the ship itself, big enough to see the differences
+        if let Some(image) = sprites.0.get(&ship.sprites.normal) {
+            let scale = 6.0;
+            graphics::draw(
+                ctx,
+                image,
+                DrawParam::new()
+                    .dest([
+                        SCREEN_WIDTH / 2.0 - f32::from(image.width()) * scale / 2.0,
+                        SCREEN_HEIGHT / 3.0,
+                    ])
+                    .scale([scale, scale]),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn cycle(current: usize, change: i32, len: usize) -> usize {
+    let change = if change < 0 { len - 1 } else { 1 };
+    (current + change) % len
+}
+
+fn volume_label(name: &str, volume: f32) -> String {
+    format!("{}: < {:.0}% >", name, volume * 100.0)
+}
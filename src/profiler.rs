@@ -0,0 +1,224 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::path::Path;
+use std::thread::ThreadId;
+use std::time::{Duration, Instant};
+
+// a minute of frames at 60 fps
+pub const DEFAULT_CAPACITY: usize = 3600;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    pub name: &'static str,
+    // an index into the threads the profiler has seen, for the trace viewer
+    pub thread: usize,
+    // since the profiler was created
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Frame {
+    pub index: u64,
+    pub samples: Vec<Sample>,
+}
+
+impl Frame {
+    // the time from the first sample starting to the last one ending
+    pub fn duration(&self) -> Duration {
+        let start = self.samples.iter().map(|sample| sample.start).min();
+        let end = self
+            .samples
+            .iter()
+            .map(|sample| sample.start + sample.duration)
+            .max();
+        match (start, end) {
+            (Some(start), Some(end)) => end - start,
+            _ => Duration::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Summary {
+    pub name: &'static str,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+// keeps the timings of the last `capacity` frames. systems record samples as they run and
+// `end_frame` moves them into the ring buffer
+#[derive(Clone, Debug)]
+pub struct Profiler {
+    epoch: Instant,
+    capacity: usize,
+    frames: VecDeque<Frame>,
+    current: Frame,
+    threads: Vec<ThreadId>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl Profiler {
+    pub fn new(capacity: usize) -> Self {
+        Profiler {
+            epoch: Instant::now(),
+            capacity: capacity.max(1),
+            frames: VecDeque::with_capacity(capacity),
+            current: Frame::default(),
+            threads: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, name: &'static str, start: Instant, duration: Duration) {
+        let id = std::thread::current().id();
+        let thread = match self.threads.iter().position(|thread| *thread == id) {
+            Some(thread) => thread,
+            None => {
+                self.threads.push(id);
+                self.threads.len() - 1
+            }
+        };
+
+        self.current.samples.push(Sample {
+            name,
+            thread,
+            start: start.saturating_duration_since(self.epoch),
+            duration,
+        });
+    }
+
+    pub fn end_frame(&mut self) {
+        let index = self.current.index + 1;
+        let frame = std::mem::replace(
+            &mut self.current,
+            Frame {
+                index,
+                samples: Vec::new(),
+            },
+        );
+
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+    }
+
+    pub fn last_frame(&self) -> Option<&Frame> {
+        self.frames.back()
+    }
+
+    // percentiles of every system over the frames in the buffer, slowest first.
+    // "frame" is the whole frame
+    pub fn summarize(&self) -> Vec<Summary> {
+        let mut times: HashMap<&'static str, Vec<Duration>> = HashMap::new();
+        self.frames.iter().for_each(|frame| {
+            times.entry("frame").or_default().push(frame.duration());
+            frame.samples.iter().for_each(|sample| {
+                times.entry(sample.name).or_default().push(sample.duration);
+            });
+        });
+
+        let mut summaries: Vec<Summary> = times
+            .into_iter()
+            .map(|(name, mut times)| {
+                times.sort();
+                Summary {
+                    name,
+                    p50: percentile(&times, 0.5),
+                    p99: percentile(&times, 0.99),
+                    max: times.last().copied().unwrap_or_default(),
+                }
+            })
+            .collect();
+        summaries.sort_by(|a, b| b.p99.cmp(&a.p99).then(a.name.cmp(b.name)));
+        summaries
+    }
+
+    // the chrome tracing json format, which can be opened at chrome://tracing or in perfetto
+    pub fn chrome_trace(&self) -> String {
+        let mut trace = String::from("{\"traceEvents\":[\n");
+        let events: Vec<String> = self
+            .frames
+            .iter()
+            .flat_map(|frame| {
+                frame.samples.iter().map(move |sample| {
+                    format!(
+                        "{{\"name\":\"{}\",\"cat\":\"system\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":1,\"tid\":{},\"args\":{{\"frame\":{}}}}}",
+                        sample.name,
+                        sample.start.as_micros(),
+                        sample.duration.as_micros(),
+                        sample.thread,
+                        frame.index
+                    )
+                })
+            })
+            .collect();
+        trace.push_str(&events.join(",\n"));
+        trace.push_str("\n]}\n");
+        trace
+    }
+
+    pub fn csv(&self) -> String {
+        let mut csv = String::from("frame,system,thread,start_us,duration_us\n");
+        self.frames.iter().for_each(|frame| {
+            frame.samples.iter().for_each(|sample| {
+                writeln!(
+                    csv,
+                    "{},{},{},{},{}",
+                    frame.index,
+                    sample.name,
+                    sample.thread,
+                    sample.start.as_micros(),
+                    sample.duration.as_micros()
+                )
+                .expect("error formatting csv");
+            });
+        });
+        csv
+    }
+
+    // writes `<output>.json` and `<output>.csv`
+    pub fn export(&self, output: &Path) -> Result<(), String> {
+        let write = |extension: &str, contents: String| {
+            let path = output.with_extension(extension);
+            std::fs::write(&path, contents)
+                .map_err(|e| format!("error writing {}: {}", path.display(), e))
+        };
+        write("json", self.chrome_trace())?;
+        write("csv", self.csv())
+    }
+}
+
+fn percentile(sorted: &[Duration], percentile: f32) -> Duration {
+    if sorted.is_empty() {
+        return Duration::default();
+    }
+    let index = ((sorted.len() - 1) as f32 * percentile).round() as usize;
+    sorted[index]
+}
+
+pub fn format_summaries(summaries: &[Summary]) -> String {
+    let mut table = format!(
+        "{:<24} {:>10} {:>10} {:>10}\n",
+        "system", "p50 ms", "p99 ms", "max ms"
+    );
+    summaries.iter().for_each(|summary| {
+        let ms = |time: Duration| time.as_secs_f64() * 1000.0;
+        writeln!(
+            table,
+            "{:<24} {:>10.3} {:>10.3} {:>10.3}",
+            summary.name,
+            ms(summary.p50),
+            ms(summary.p99),
+            ms(summary.max)
+        )
+        .expect("error formatting summary");
+    });
+    table
+}
@@ -0,0 +1,487 @@
+use ggez::{
+    graphics::{self, Color, DrawMode, DrawParam, Font, Image, MeshBuilder, Rect, Scale, Text},
+    Context, GameResult,
+};
+use specs::prelude::*;
+
+use std::sync::{Arc, Mutex};
+
+use crate::ecs::components::*;
+use crate::ecs::resources::*;
+use crate::{PIXEL_SCALE, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+#[cfg(test)]
+mod raster;
+#[cfg(test)]
+mod tests;
+
+// where the pixels of a draw command come from
+#[derive(Clone)]
+pub enum Texture {
+    // a key in the manifest's sprites
+    Sprite(String),
+    Sheet(Arc<Mutex<SpriteSheet>>),
+    // a key in the manifest's animations, with its image unless running headless. the key is
+    // only read by the cpu rasterizer
+    #[cfg_attr(not(test), allow(dead_code))]
+    Animation(String, Option<Image>),
+}
+
+// the game and the hud are turned into a list of these before drawing, so that they can be
+// drawn with ggez or by the cpu rasterizer in the golden image tests
+#[derive(Clone)]
+pub enum DrawCommand {
+    // src goes from 0 to 1 over the texture, like ggez's
+    Image {
+        texture: Texture,
+        src: Rect,
+        dest: Point,
+        scale: Vector,
+        color: Color,
+    },
+    // the whole texture stretched over dest
+    Stretch {
+        texture: Texture,
+        dest: Rect,
+        color: Color,
+    },
+    // filled if there's no stroke width
+    Rect {
+        rect: Rect,
+        stroke: Option<f32>,
+        color: Color,
+    },
+    Ring {
+        center: Point,
+        radius: f32,
+        width: f32,
+        color: Color,
+    },
+    Line {
+        points: Vec<Point>,
+        width: f32,
+        color: Color,
+    },
+    // dest is the top left corner
+    Text {
+        text: String,
+        size: f32,
+        dest: Point,
+        color: Color,
+    },
+}
+
+// text and texture sizes, which layouts need before anything is drawn
+pub trait Measure {
+    fn text_size(&mut self, text: &str, size: f32) -> (f32, f32);
+    // the size of the whole texture in pixels
+    fn texture_size(&mut self, texture: &Texture) -> (f32, f32);
+}
+
+// the playfield in the order it's drawn in, in game coordinates without the camera offset
+pub fn world_commands(world: &World) -> Vec<DrawCommand> {
+    let mut commands = Vec::new();
+
+    {
+        // star layers can have background images, these go behind everything else
+        let star_info = world.fetch::<StarInfo>();
+        let stages = [
+            (star_info.previous_stage, 1.0 - star_info.fade),
+            (star_info.current_stage, star_info.fade),
+        ];
+
+        stages
+            .iter()
+            .filter(|(_, alpha)| *alpha > 0.0)
+            .for_each(|(stage, alpha)| {
+                star_info
+                    .layers(*stage)
+                    .iter()
+                    .zip(star_info.scroll(*stage).iter())
+                    .filter_map(|(layer, scroll)| Some((layer.image.as_ref()?, scroll)))
+                    .for_each(|(key, scroll)| {
+                        // stretched over the screen and tiled vertically
+                        [*scroll - SCREEN_HEIGHT, *scroll].iter().for_each(|y| {
+                            commands.push(DrawCommand::Stretch {
+                                texture: Texture::Sprite(key.clone()),
+                                dest: Rect::new(0.0, *y, SCREEN_WIDTH, SCREEN_HEIGHT),
+                                color: Color::new(1.0, 1.0, 1.0, *alpha),
+                            });
+                        });
+                    });
+            });
+    }
+
+    let positions = world.read_storage::<Position>();
+    let colorects = world.read_storage::<ColorRect>();
+    let sprites = world.read_storage::<Sprite>();
+    let stars = world.read_storage::<Star>();
+    let particles = world.read_storage::<Particle>();
+    let hp_storage = world.read_storage::<HP>();
+    let entities = world.entities();
+    let animated_sprite_storage = world.read_storage::<AnimatedSprite>();
+    let scale = Vector::new(PIXEL_SCALE, PIXEL_SCALE);
+
+    // spritesheet instances go after the other sprites, sorted by their image so that each
+    // spritesheet is drawn in one batch
+    let mut sheet_instances = Vec::new();
+    (&positions, &sprites, &entities)
+        .join()
+        .for_each(|(pos, sprite, entity)| {
+            // sprites with iframes remaining should be lower opacity
+            let color = if let Some(hp) = hp_storage.get(entity) {
+                let opacity = (60 - hp.iframes as u32) as f32 / 60.0;
+                Color::new(1.0, 1.0, 1.0, opacity.powi(5))
+            } else {
+                graphics::WHITE
+            };
+
+            match sprite {
+                Sprite::Img(key) => commands.push(DrawCommand::Image {
+                    texture: Texture::Sprite(key.clone()),
+                    src: Rect::one(),
+                    dest: pos.0,
+                    scale,
+                    color,
+                }),
+                Sprite::SpriteSheetInstance(spritesheet, index) => {
+                    let (image, src, pivot) = {
+                        let spritesheet = spritesheet.lock().expect("error locking spritesheet");
+                        (
+                            spritesheet.atlas.image.clone(),
+                            spritesheet.src_rect(*index),
+                            spritesheet.pivot(*index),
+                        )
+                    };
+                    let command = DrawCommand::Image {
+                        texture: Texture::Sheet(spritesheet.clone()),
+                        src,
+                        dest: pos.0 - pivot,
+                        scale,
+                        color,
+                    };
+                    sheet_instances.push((image, command));
+                }
+            }
+        });
+
+    (&positions, &animated_sprite_storage)
+        .join()
+        .for_each(|(pos, animated_sprite)| {
+            let frame_width = 1.0 / animated_sprite.spritesheet_width as f32;
+            commands.push(DrawCommand::Image {
+                texture: Texture::Animation(
+                    animated_sprite.key.clone(),
+                    animated_sprite.spritesheet.clone(),
+                ),
+                src: Rect::new(
+                    animated_sprite.current_frame as f32 * frame_width,
+                    0.0,
+                    frame_width,
+                    1.0,
+                ),
+                dest: pos.0,
+                scale,
+                color: graphics::WHITE,
+            });
+        });
+
+    sheet_instances.sort_by(|(a, _), (b, _)| a.cmp(b));
+    commands.extend(sheet_instances.into_iter().map(|(_, command)| command));
+
+    // stars need to be drawn first so they stay in the background,
+    // in practice they're the only colorects so it doesn't matter
+    let colorect_command = |pos: &Position, colorect: &ColorRect| DrawCommand::Rect {
+        rect: Rect::new(
+            pos.0.x,
+            pos.0.y,
+            colorect.w.max(PIXEL_SCALE),
+            colorect.h.max(PIXEL_SCALE),
+        ),
+        stroke: None,
+        color: colorect.color,
+    };
+    (&positions, &colorects, &stars)
+        .join()
+        .filter(|(pos, _, _)| pos.0.y > 0.0)
+        .for_each(|(pos, colorect, _)| commands.push(colorect_command(pos, colorect)));
+    (&positions, &colorects, !&stars)
+        .join()
+        .for_each(|(pos, colorect, _)| commands.push(colorect_command(pos, colorect)));
+
+    // particles are just squares centered on their position
+    (&positions, &particles).join().for_each(|(pos, particle)| {
+        let size = particle.size().max(PIXEL_SCALE);
+        commands.push(DrawCommand::Rect {
+            rect: Rect::new(pos.0.x - size / 2.0, pos.0.y - size / 2.0, size, size),
+            stroke: None,
+            color: particle.color(),
+        });
+    });
+
+    let hitboxes = world.read_storage::<Hitbox>();
+    let hitbox_rect = |pos: &Position, hitbox: &Hitbox| {
+        Rect::new(
+            pos.0.x + hitbox.0.x,
+            pos.0.y + hitbox.0.y,
+            hitbox.1,
+            hitbox.2,
+        )
+    };
+
+    // focusing outlines the whole area where the player can be hit, with a dot in its middle
+    (&positions, &hitboxes, &world.read_storage::<Player>())
+        .join()
+        .filter(|(_, _, player)| player.focused)
+        .for_each(|(pos, hitbox, _)| {
+            let rect = hitbox_rect(pos, hitbox);
+            let center = Point::new(rect.x + rect.w / 2.0, rect.y + rect.h / 2.0);
+            commands.push(DrawCommand::Rect {
+                rect,
+                stroke: Some(2.0),
+                color: Color::new(1.0, 0.3, 0.3, 0.8),
+            });
+            commands.push(DrawCommand::Rect {
+                rect: Rect::new(center.x - 3.0, center.y - 3.0, 6.0, 6.0),
+                stroke: None,
+                color: graphics::WHITE,
+            });
+        });
+
+    // how far the deflector reaches, faint while it's charging
+    (&positions, &hitboxes, &world.read_storage::<Player>())
+        .join()
+        .for_each(|(pos, hitbox, player)| {
+            let (level, alpha) = if player.deflector_timer > 0 {
+                (player.deflector_level, 0.8)
+            } else if player.deflector_held_frames > 0 {
+                (player.charge_level(), 0.35)
+            } else {
+                return;
+            };
+            let rect = hitbox_rect(pos, hitbox);
+            commands.push(DrawCommand::Ring {
+                center: Point::new(rect.x + rect.w / 2.0, rect.y + rect.h / 2.0),
+                radius: rect.w.max(rect.h) / 2.0 + player.deflector_radius(level),
+                width: level as f32 + 1.0,
+                color: Color::new(0.5, 0.9, 1.0, alpha),
+            });
+        });
+
+    if cfg!(feature = "draw_hitboxes") {
+        (&positions, &hitboxes).join().for_each(|(pos, hitbox)| {
+            commands.push(DrawCommand::Rect {
+                rect: hitbox_rect(pos, hitbox),
+                stroke: Some(2.5),
+                color: Color::new(1.0, 0.0, 0.0, 1.0),
+            });
+        });
+    }
+
+    commands
+}
+
+// draws with ggez. spritesheet instances are batched and shapes are built into one mesh, until
+// a command that's drawn on its own comes between them
+pub fn draw(ctx: &mut Context, world: &World, commands: &[DrawCommand]) -> GameResult {
+    let sprites = &world.fetch::<Sprites>().0;
+    let font = world.fetch::<GameFont>().0;
+    let mut batched: Vec<Arc<Mutex<SpriteSheet>>> = Vec::new();
+    let mut builder: Option<MeshBuilder> = None;
+
+    for command in commands {
+        match command {
+            DrawCommand::Image {
+                texture: Texture::Sheet(_),
+                ..
+            } => flush_mesh(ctx, &mut builder)?,
+            DrawCommand::Rect { .. } | DrawCommand::Ring { .. } | DrawCommand::Line { .. } => {
+                flush_batches(ctx, &mut batched)?
+            }
+            _ => {
+                flush_batches(ctx, &mut batched)?;
+                flush_mesh(ctx, &mut builder)?;
+            }
+        }
+
+        match command {
+            DrawCommand::Image {
+                texture: Texture::Sheet(spritesheet),
+                src,
+                dest,
+                scale,
+                color,
+            } => {
+                if let Some(batch) =
+                    &mut spritesheet.lock().expect("error locking spritesheet").batch
+                {
+                    batch.add(
+                        DrawParam::new()
+                            .src(*src)
+                            .scale([scale.x, scale.y])
+                            .dest(*dest)
+                            .color(*color),
+                    );
+                }
+                if !batched.iter().any(|other| Arc::ptr_eq(other, spritesheet)) {
+                    batched.push(spritesheet.clone());
+                }
+            }
+            DrawCommand::Image {
+                texture,
+                src,
+                dest,
+                scale,
+                color,
+            } => {
+                if let Some(image) = ggez_image(sprites, texture) {
+                    graphics::draw(
+                        ctx,
+                        image,
+                        DrawParam::new()
+                            .src(*src)
+                            .scale([scale.x, scale.y])
+                            .dest(*dest)
+                            .color(*color),
+                    )?;
+                }
+            }
+            DrawCommand::Stretch {
+                texture,
+                dest,
+                color,
+            } => {
+                if let Some(image) = ggez_image(sprites, texture) {
+                    let scale = [
+                        dest.w / f32::from(image.width()),
+                        dest.h / f32::from(image.height()),
+                    ];
+                    graphics::draw(
+                        ctx,
+                        image,
+                        DrawParam::new()
+                            .scale(scale)
+                            .dest([dest.x, dest.y])
+                            .color(*color),
+                    )?;
+                }
+            }
+            DrawCommand::Rect {
+                rect,
+                stroke,
+                color,
+            } => {
+                let mode = stroke.map_or_else(DrawMode::fill, DrawMode::stroke);
+                builder
+                    .get_or_insert_with(MeshBuilder::new)
+                    .rectangle(mode, *rect, *color);
+            }
+            DrawCommand::Ring {
+                center,
+                radius,
+                width,
+                color,
+            } => {
+                builder.get_or_insert_with(MeshBuilder::new).circle(
+                    DrawMode::stroke(*width),
+                    *center,
+                    *radius,
+                    0.5,
+                    *color,
+                );
+            }
+            DrawCommand::Line {
+                points,
+                width,
+                color,
+            } => {
+                if points.len() >= 2 {
+                    builder
+                        .get_or_insert_with(MeshBuilder::new)
+                        .line(points, *width, *color)?;
+                }
+            }
+            DrawCommand::Text {
+                text,
+                size,
+                dest,
+                color,
+            } => {
+                let text = make_text(text, font, *size);
+                graphics::draw(ctx, &text, DrawParam::new().dest(*dest).color(*color))?;
+            }
+        }
+    }
+
+    flush_batches(ctx, &mut batched)?;
+    flush_mesh(ctx, &mut builder)
+}
+
+fn ggez_image<'a>(
+    sprites: &'a std::collections::HashMap<String, Image>,
+    texture: &'a Texture,
+) -> Option<&'a Image> {
+    match texture {
+        Texture::Sprite(key) => sprites.get(key),
+        Texture::Animation(_, image) => image.as_ref(),
+        Texture::Sheet(_) => None,
+    }
+}
+
+fn flush_batches(ctx: &mut Context, batched: &mut Vec<Arc<Mutex<SpriteSheet>>>) -> GameResult {
+    batched.drain(..).try_for_each(|spritesheet| {
+        let mut spritesheet = spritesheet.lock().expect("error locking spritesheet");
+        match &mut spritesheet.batch {
+            Some(batch) => {
+                graphics::draw(ctx, batch, DrawParam::new())?;
+                batch.clear();
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    })
+}
+
+fn flush_mesh(ctx: &mut Context, builder: &mut Option<MeshBuilder>) -> GameResult {
+    match builder.take() {
+        Some(builder) => {
+            let mesh = builder.build(ctx)?;
+            graphics::draw(ctx, &mesh, DrawParam::new())
+        }
+        None => Ok(()),
+    }
+}
+
+pub fn make_text(text: &str, font: Font, size: f32) -> Text {
+    let mut text = Text::new(text);
+    text.set_font(font, Scale::uniform(size));
+    text
+}
+
+// measures with ggez, for drawing in the game
+pub struct GgezMeasure<'a> {
+    pub ctx: &'a mut Context,
+    pub world: &'a World,
+}
+
+impl Measure for GgezMeasure<'_> {
+    fn text_size(&mut self, text: &str, size: f32) -> (f32, f32) {
+        let font = self.world.fetch::<GameFont>().0;
+        let (w, h) = make_text(text, font, size).dimensions(self.ctx);
+        (w as f32, h as f32)
+    }
+
+    fn texture_size(&mut self, texture: &Texture) -> (f32, f32) {
+        if let Texture::Sheet(spritesheet) = texture {
+            let (w, h) = spritesheet
+                .lock()
+                .expect("error locking spritesheet")
+                .image_size;
+            return (f32::from(w), f32::from(h));
+        }
+        let sprites = &self.world.fetch::<Sprites>().0;
+        ggez_image(sprites, texture)
+            .map(|image| (f32::from(image.width()), f32::from(image.height())))
+            .unwrap_or((0.0, 0.0))
+    }
+}
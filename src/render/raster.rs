@@ -0,0 +1,307 @@
+use ggez::graphics::{Color, Rect};
+use image::{Rgba, RgbaImage};
+use rusttype::{point, Font, Scale};
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use super::{DrawCommand, Measure, Texture};
+use crate::assets::{self, AssetManifest, MANIFEST_PATH};
+use crate::ecs::components::{Point, Vector};
+
+// draws commands into an image on the cpu, for tests on machines without a gpu. textures are
+// sampled with the nearest pixel like the game's, but shapes and text aren't antialiased the
+// same way, so it only matches its own output
+pub struct Raster {
+    pub image: RgbaImage,
+    // the part of the game that's drawn, and how many pixels there are per game unit
+    view: Rect,
+    scale: f32,
+    resources_dir: PathBuf,
+    manifest: AssetManifest,
+    textures: HashMap<String, Rc<RgbaImage>>,
+    font: Font<'static>,
+}
+
+impl Raster {
+    pub fn new(resources_dir: &Path, view: Rect, scale: f32) -> Result<Self, String> {
+        let manifest = AssetManifest::from_file(assets::resolve(resources_dir, MANIFEST_PATH))?;
+        let font_path = manifest
+            .fonts
+            .get("main")
+            .ok_or("font main is missing from the manifest")?;
+        let font_bytes = std::fs::read(assets::resolve(resources_dir, font_path))
+            .map_err(|e| format!("error reading font {}: {}", font_path, e))?;
+        let font = Font::from_bytes(font_bytes)
+            .map_err(|e| format!("error parsing font {}: {}", font_path, e))?;
+
+        let width = (view.w * scale).round() as u32;
+        let height = (view.h * scale).round() as u32;
+        Ok(Raster {
+            image: RgbaImage::from_pixel(width, height, Rgba([0, 0, 0, 255])),
+            view,
+            scale,
+            resources_dir: resources_dir.to_path_buf(),
+            manifest,
+            textures: HashMap::new(),
+            font,
+        })
+    }
+
+    pub fn draw(&mut self, commands: &[DrawCommand]) {
+        commands.iter().for_each(|command| match command {
+            DrawCommand::Image {
+                texture,
+                src,
+                dest,
+                scale,
+                color,
+            } => {
+                if let Some(texture) = self.texture(texture) {
+                    self.draw_texture(&texture, *src, *dest, *scale, *color);
+                }
+            }
+            DrawCommand::Stretch {
+                texture,
+                dest,
+                color,
+            } => {
+                if let Some(texture) = self.texture(texture) {
+                    let scale = Vector::new(
+                        dest.w / texture.width() as f32,
+                        dest.h / texture.height() as f32,
+                    );
+                    let dest = Point::new(dest.x, dest.y);
+                    self.draw_texture(&texture, Rect::one(), dest, scale, *color);
+                }
+            }
+            DrawCommand::Rect {
+                rect,
+                stroke: None,
+                color,
+            } => self.shade(*rect, |_| Some(*color)),
+            DrawCommand::Rect {
+                rect,
+                stroke: Some(width),
+                color,
+            } => {
+                // strokes are centered on the edges, like ggez's
+                let half = width / 2.0;
+                let outer = Rect::new(rect.x - half, rect.y - half, rect.w + width, rect.h + width);
+                let inner = Rect::new(rect.x + half, rect.y + half, rect.w - width, rect.h - width);
+                self.shade(outer, |pos| {
+                    if inner.contains(pos) {
+                        None
+                    } else {
+                        Some(*color)
+                    }
+                });
+            }
+            DrawCommand::Ring {
+                center,
+                radius,
+                width,
+                color,
+            } => {
+                let outer = radius + width / 2.0;
+                let area = Rect::new(center.x - outer, center.y - outer, outer * 2.0, outer * 2.0);
+                self.shade(area, |pos| {
+                    if ((pos - center).norm() - radius).abs() <= width / 2.0 {
+                        Some(*color)
+                    } else {
+                        None
+                    }
+                });
+            }
+            DrawCommand::Line {
+                points,
+                width,
+                color,
+            } => {
+                // segments share their ends, so the joints are only drawn once
+                let half = width / 2.0;
+                let (min, max) = points.iter().fold(
+                    (
+                        Point::new(f32::MAX, f32::MAX),
+                        Point::new(f32::MIN, f32::MIN),
+                    ),
+                    |(min, max), point| {
+                        (
+                            Point::new(min.x.min(point.x), min.y.min(point.y)),
+                            Point::new(max.x.max(point.x), max.y.max(point.y)),
+                        )
+                    },
+                );
+                let area = Rect::new(
+                    min.x - half,
+                    min.y - half,
+                    max.x - min.x + width,
+                    max.y - min.y + width,
+                );
+                self.shade(area, |pos| {
+                    let on_line = points
+                        .windows(2)
+                        .any(|segment| distance_to_segment(pos, segment[0], segment[1]) <= half);
+                    if on_line {
+                        Some(*color)
+                    } else {
+                        None
+                    }
+                });
+            }
+            DrawCommand::Text {
+                text,
+                size,
+                dest,
+                color,
+            } => self.draw_text(text, *size, *dest, *color),
+        });
+    }
+
+    fn texture(&mut self, texture: &Texture) -> Option<Rc<RgbaImage>> {
+        let path = match texture {
+            Texture::Sprite(key) => self.manifest.sprites.get(key)?.clone(),
+            Texture::Sheet(spritesheet) => spritesheet
+                .lock()
+                .expect("error locking spritesheet")
+                .atlas
+                .image
+                .clone(),
+            Texture::Animation(key, _) => self.manifest.animations.get(key)?.image.clone(),
+        };
+
+        if !self.textures.contains_key(&path) {
+            let image = image::open(assets::resolve(&self.resources_dir, &path))
+                .map_err(|e| log::warn!("error opening {}: {}", path, e))
+                .ok()?
+                .to_rgba();
+            self.textures.insert(path.clone(), Rc::new(image));
+        }
+        self.textures.get(&path).cloned()
+    }
+
+    fn draw_texture(
+        &mut self,
+        texture: &RgbaImage,
+        src: Rect,
+        dest: Point,
+        scale: Vector,
+        color: Color,
+    ) {
+        let (texture_w, texture_h) = (texture.width() as f32, texture.height() as f32);
+        let src = Rect::new(
+            src.x * texture_w,
+            src.y * texture_h,
+            src.w * texture_w,
+            src.h * texture_h,
+        );
+        let area = Rect::new(dest.x, dest.y, src.w * scale.x, src.h * scale.y);
+        self.shade(area, |pos| {
+            let x = (src.x + (pos.x - dest.x) / scale.x).floor();
+            let y = (src.y + (pos.y - dest.y) / scale.y).floor();
+            let x = x.max(src.x).min(src.right() - 1.0).max(0.0) as u32;
+            let y = y.max(src.y).min(src.bottom() - 1.0).max(0.0) as u32;
+            let Rgba([r, g, b, a]) =
+                *texture.get_pixel(x.min(texture.width() - 1), y.min(texture.height() - 1));
+            Some(Color::new(
+                f32::from(r) / 255.0 * color.r,
+                f32::from(g) / 255.0 * color.g,
+                f32::from(b) / 255.0 * color.b,
+                f32::from(a) / 255.0 * color.a,
+            ))
+        });
+    }
+
+    fn draw_text(&mut self, text: &str, size: f32, dest: Point, color: Color) {
+        let scale = Scale::uniform(size * self.scale);
+        let ascent = self.font.v_metrics(scale).ascent;
+        let start = point(
+            (dest.x - self.view.x) * self.scale,
+            (dest.y - self.view.y) * self.scale + ascent,
+        );
+
+        let glyphs: Vec<_> = self.font.layout(text, scale, start).collect();
+        glyphs.iter().for_each(|glyph| {
+            if let Some(bounds) = glyph.pixel_bounding_box() {
+                glyph.draw(|x, y, coverage| {
+                    let color = Color::new(color.r, color.g, color.b, color.a * coverage);
+                    self.blend(bounds.min.x + x as i32, bounds.min.y + y as i32, color);
+                });
+            }
+        });
+    }
+
+    // calls `shader` with the center of every pixel in `area`, in game coordinates
+    fn shade(&mut self, area: Rect, mut shader: impl FnMut(Point) -> Option<Color>) {
+        let to_pixel = |x: f32, origin: f32| ((x - origin) * self.scale).round() as i32;
+        let (left, right) = (
+            to_pixel(area.x, self.view.x),
+            to_pixel(area.right(), self.view.x),
+        );
+        let (top, bottom) = (
+            to_pixel(area.y, self.view.y),
+            to_pixel(area.bottom(), self.view.y),
+        );
+
+        (top.max(0)..bottom.min(self.image.height() as i32)).for_each(|y| {
+            (left.max(0)..right.min(self.image.width() as i32)).for_each(|x| {
+                let pos = Point::new(
+                    self.view.x + (x as f32 + 0.5) / self.scale,
+                    self.view.y + (y as f32 + 0.5) / self.scale,
+                );
+                if let Some(color) = shader(pos) {
+                    self.blend(x, y, color);
+                }
+            });
+        });
+    }
+
+    fn blend(&mut self, x: i32, y: i32, color: Color) {
+        if x < 0 || y < 0 || x >= self.image.width() as i32 || y >= self.image.height() as i32 {
+            return;
+        }
+        let pixel = self.image.get_pixel_mut(x as u32, y as u32);
+        let alpha = color.a.clamp(0.0, 1.0);
+        let mix = |src: f32, dst: u8| {
+            (src.clamp(0.0, 1.0) * 255.0 * alpha + f32::from(dst) * (1.0 - alpha)).round() as u8
+        };
+        let Rgba([r, g, b, a]) = *pixel;
+        *pixel = Rgba([
+            mix(color.r, r),
+            mix(color.g, g),
+            mix(color.b, b),
+            mix(1.0, a),
+        ]);
+    }
+}
+
+impl Measure for Raster {
+    fn text_size(&mut self, text: &str, size: f32) -> (f32, f32) {
+        let scale = Scale::uniform(size);
+        let v_metrics = self.font.v_metrics(scale);
+        let width = self
+            .font
+            .layout(text, scale, point(0.0, 0.0))
+            .last()
+            .map(|glyph| glyph.position().x + glyph.unpositioned().h_metrics().advance_width)
+            .unwrap_or(0.0);
+        (width.ceil(), (v_metrics.ascent - v_metrics.descent).ceil())
+    }
+
+    fn texture_size(&mut self, texture: &Texture) -> (f32, f32) {
+        self.texture(texture)
+            .map(|texture| (texture.width() as f32, texture.height() as f32))
+            .unwrap_or((0.0, 0.0))
+    }
+}
+
+fn distance_to_segment(pos: Point, a: Point, b: Point) -> f32 {
+    let ab = b - a;
+    let length_squared = ab.norm_squared();
+    if length_squared == 0.0 {
+        return (pos - a).norm();
+    }
+    let t = ((pos - a).dot(&ab) / length_squared).clamp(0.0, 1.0);
+    (pos - (a + ab * t)).norm()
+}
@@ -0,0 +1,206 @@
+use super::raster::Raster;
+use super::*;
+use crate::{assets, hud, sim};
+
+use image::RgbaImage;
+use std::path::{Path, PathBuf};
+
+// a pixel counts as different if any channel is further off than this, and a scene fails if
+// more than this fraction of its pixels are different
+const CHANNEL_TOLERANCE: u8 = 24;
+const MAX_DIFFERENT_PIXELS: f32 = 0.002;
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", name))
+}
+
+// compares against tests/golden/<name>.png. with UPDATE_GOLDEN set the golden image is
+// written instead, for when a change to the rendering is intended
+fn check_golden(name: &str, image: &RgbaImage) {
+    let path = golden_path(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        image.save(&path).expect("error saving golden image");
+        return;
+    }
+
+    let golden = image::open(&path)
+        .unwrap_or_else(|e| {
+            panic!(
+                "error opening {}: {}, run with UPDATE_GOLDEN=1 to create it",
+                path.display(),
+                e
+            )
+        })
+        .to_rgba();
+    assert_eq!(
+        golden.dimensions(),
+        image.dimensions(),
+        "{} changed size",
+        name
+    );
+
+    let different = golden
+        .pixels()
+        .zip(image.pixels())
+        .filter(|(a, b)| {
+            a.0.iter()
+                .zip(b.0.iter())
+                .any(|(a, b)| (i16::from(*a) - i16::from(*b)).abs() > i16::from(CHANNEL_TOLERANCE))
+        })
+        .count();
+    let total = (image.width() * image.height()) as f32;
+    if different as f32 > total * MAX_DIFFERENT_PIXELS {
+        let actual = std::env::temp_dir().join(format!("{}.actual.png", name));
+        image.save(&actual).expect("error saving actual image");
+        panic!(
+            "{} has {} pixels different from {}, the actual image is at {}",
+            name,
+            different,
+            path.display(),
+            actual.display()
+        );
+    }
+}
+
+// the player, every enemy and bullet type and an explosion, in a seeded world
+fn scene() -> World {
+    let mut world = sim::create_world();
+    let errors = assets::load_headless(Path::new(crate::RESOURCES_DIR), &mut world);
+    assert!(errors.is_empty(), "error loading assets: {:?}", errors);
+    world.insert(GameRng::seeded(0));
+    sim::spawn_player(&mut world, 3);
+
+    ENEMY_TYPES.iter().enumerate().for_each(|(i, ty)| {
+        let x = 20.0 + i as f32 * 105.0;
+        let enemy = new_enemy(
+            *ty,
+            Point::new(x, 80.0),
+            MovementType::horizontal(x, 75.0, 1.0),
+            &world.fetch::<Balance>(),
+            &mut world.fetch_mut::<GameRng>().0,
+        );
+        create_enemy(&mut world, enemy);
+    });
+
+    let bullet_types = [
+        BulletType::PlayerBullet,
+        BulletType::BasicBullet,
+        BulletType::AimedBullet,
+        BulletType::PredictBullet,
+        BulletType::TrackingBullet(10),
+        BulletType::BouncingBullet(2),
+    ];
+    let spritesheet = world.fetch::<SpriteSheets>().0["bullets"].clone();
+    bullet_types.iter().enumerate().for_each(|(i, ty)| {
+        let (pos, vel, bullet, frame) = new_bullet(
+            *ty,
+            Point::new(120.0 + i as f32 * 100.0, 360.0),
+            Vector::new(0.0, 0.0),
+            DamagesWho::Player,
+        );
+        let (sprite, hitbox) = spritesheet_frame(&spritesheet, frame);
+        world
+            .create_entity()
+            .with(pos)
+            .with(hitbox)
+            .with(vel)
+            .with(bullet)
+            .with(sprite)
+            .build();
+    });
+
+    let mut explosion = world.fetch::<AnimatedSprites>().0["explosion"].clone();
+    explosion.current_frame = 4;
+    world
+        .create_entity()
+        .with(Position(Point::new(560.0, 460.0)))
+        .with(explosion)
+        .build();
+
+    world
+        .create_entity()
+        .with(Position(Point::new(90.0, 500.0)))
+        .with(ColorRect {
+            color: Color::new(0.8, 0.9, 1.0, 1.0),
+            w: 6.0,
+            h: 6.0,
+        })
+        .build();
+
+    *world.fetch_mut::<Score>() = Score {
+        current: 12345,
+        high: 99999,
+    };
+    world.insert(GrazeCount(42));
+    let player_entity = world.fetch::<PlayerEntity>().0;
+    world
+        .write_storage::<Player>()
+        .get_mut(player_entity)
+        .expect("error getting player")
+        .deflector_cooldown = 60;
+
+    world
+}
+
+// the game, with the hud over it if it's drawn at `view`
+fn render(world: &World, view: Rect, scale: f32, with_hud: bool) -> RgbaImage {
+    let mut raster = Raster::new(Path::new(crate::RESOURCES_DIR), view, scale)
+        .expect("error creating rasterizer");
+    raster.draw(&world_commands(world));
+    if with_hud {
+        let commands = hud::commands(world, view, &mut raster);
+        raster.draw(&commands);
+    }
+    raster.image
+}
+
+const PLAYFIELD: Rect = Rect {
+    x: 0.0,
+    y: 0.0,
+    w: SCREEN_WIDTH,
+    h: SCREEN_HEIGHT,
+};
+
+#[test]
+fn playfield_at_native_resolution() {
+    let image = render(&scene(), PLAYFIELD, 1.0 / PIXEL_SCALE, false);
+    check_golden("playfield", &image);
+}
+
+#[test]
+fn hud_over_the_playfield() {
+    let image = render(&scene(), PLAYFIELD, 0.5, true);
+    check_golden("hud_playfield", &image);
+}
+
+#[test]
+fn hud_in_side_panels() {
+    let view = Rect::new(-240.0, 0.0, SCREEN_WIDTH + 480.0, SCREEN_HEIGHT);
+    let image = render(&scene(), view, 0.5, true);
+    check_golden("hud_side_panels", &image);
+}
+
+#[test]
+fn hud_between_waves() {
+    let mut world = scene();
+    world.delete_all();
+    sim::spawn_player(&mut world, 5);
+    world.insert(FramesToNextWave(10));
+    let image = render(&world, PLAYFIELD, 0.5, true);
+    check_golden("hud_between_waves", &image);
+}
+
+#[test]
+fn focused_player_shows_the_hitbox() {
+    let world = scene();
+    let player_entity = world.fetch::<PlayerEntity>().0;
+    world
+        .write_storage::<Player>()
+        .get_mut(player_entity)
+        .expect("error getting player")
+        .focused = true;
+    let image = render(&world, PLAYFIELD, 1.0 / PIXEL_SCALE, false);
+    check_golden("focused_player", &image);
+}
@@ -0,0 +1,89 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::ecs::resources::Difficulty;
+
+// files are saved in the same directory ggez uses for the user's config,
+// but without going through a context so that they can be read before one exists
+fn path(file: &str) -> Option<PathBuf> {
+    directories::ProjectDirs::from("", crate::AUTHOR, crate::GAME_ID)
+        .map(|dirs| dirs.config_dir().join(file))
+}
+
+// missing files give the default, broken ones give the default with a warning
+pub fn load<T: DeserializeOwned + Default>(file: &str) -> T {
+    let path = match path(file) {
+        Some(path) if path.exists() => path,
+        _ => return T::default(),
+    };
+
+    std::fs::File::open(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|file| ron::de::from_reader(file).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            log::warn!(
+                "error loading {}, using the defaults: {}",
+                path.display(),
+                e
+            );
+            T::default()
+        })
+}
+
+pub fn save<T: Serialize>(file: &str, value: &T) {
+    let path = match path(file) {
+        Some(path) => path,
+        None => {
+            log::warn!("error saving {}: no config directory", file);
+            return;
+        }
+    };
+
+    let pretty = ron::ser::PrettyConfig::default();
+    let result = ron::ser::to_string_pretty(value, pretty)
+        .map_err(|e| e.to_string())
+        .and_then(|serialized| {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+            }
+            std::fs::write(&path, serialized).map_err(|e| e.to_string())
+        });
+
+    if let Err(e) = result {
+        log::warn!("error saving {}: {}", path.display(), e);
+    }
+}
+
+// progress that's kept between runs
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveData {
+    // from before there were difficulties, it's moved to normal's when loading
+    #[serde(skip_serializing)]
+    pub high_score: u32,
+    pub high_scores: HashMap<Difficulty, u32>,
+}
+
+impl SaveData {
+    const FILE: &'static str = "save.ron";
+
+    pub fn load() -> Self {
+        let mut save_data: SaveData = load(SaveData::FILE);
+        if save_data.high_score > 0 {
+            let normal = save_data.high_scores.entry(Difficulty::Normal).or_insert(0);
+            *normal = (*normal).max(save_data.high_score);
+            save_data.high_score = 0;
+        }
+        save_data
+    }
+
+    pub fn save(&self) {
+        save(SaveData::FILE, self)
+    }
+
+    pub fn high_score(&self, difficulty: Difficulty) -> u32 {
+        self.high_scores.get(&difficulty).copied().unwrap_or(0)
+    }
+}
@@ -0,0 +1,112 @@
+use ggez::{
+    conf::NumSamples,
+    graphics::{self, Canvas, Color, DrawMode, DrawParam, FilterMode, MeshBuilder, Rect},
+    Context, GameResult,
+};
+
+use crate::{PIXEL_SCALE, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// the resolution the art is drawn at, every sprite is scaled up by PIXEL_SCALE
+pub const NATIVE_WIDTH: u16 = (SCREEN_WIDTH / PIXEL_SCALE) as u16;
+pub const NATIVE_HEIGHT: u16 = (SCREEN_HEIGHT / PIXEL_SCALE) as u16;
+
+// the game is drawn to a canvas at the native resolution which is then scaled up by a whole
+// number to fill as much of the window as possible, so that pixels stay square and sharp.
+// the hud is drawn on top at the window's resolution so that text stays readable
+pub struct PixelScreen {
+    canvas: Canvas,
+}
+
+impl PixelScreen {
+    pub fn new(ctx: &mut Context) -> GameResult<Self> {
+        let mut canvas = Canvas::new(ctx, NATIVE_WIDTH, NATIVE_HEIGHT, NumSamples::One)?;
+        canvas.set_filter(FilterMode::Nearest);
+        Ok(PixelScreen { canvas })
+    }
+
+    // everything drawn until `finish` goes to the canvas, in game coordinates
+    pub fn begin(&self, ctx: &mut Context) -> GameResult {
+        graphics::set_canvas(ctx, Some(&self.canvas));
+        graphics::set_screen_coordinates(ctx, Rect::new(0.0, 0.0, SCREEN_WIDTH, SCREEN_HEIGHT))?;
+        graphics::clear(ctx, Color::new(0.0, 0.0, 0.0, 1.0));
+        Ok(())
+    }
+
+    // draws the canvas to the window, then sets the screen coordinates so that game coordinates
+    // line up with it again. anything outside of 0..SCREEN_WIDTH is in the letterbox.
+    // returns the part of the window that's visible, in game coordinates
+    pub fn finish(&self, ctx: &mut Context, scanlines: bool) -> GameResult<Rect> {
+        graphics::set_canvas(ctx, None);
+
+        let (width, height) = graphics::drawable_size(ctx);
+        let (native_width, native_height) = (f32::from(NATIVE_WIDTH), f32::from(NATIVE_HEIGHT));
+        let scale = (width / native_width)
+            .min(height / native_height)
+            .floor()
+            .max(1.0);
+        let offset_x = ((width - native_width * scale) / 2.0).floor();
+        let offset_y = ((height - native_height * scale) / 2.0).floor();
+
+        graphics::set_screen_coordinates(ctx, Rect::new(0.0, 0.0, width, height))?;
+        graphics::clear(ctx, Color::new(0.0, 0.0, 0.0, 1.0));
+        graphics::draw(
+            ctx,
+            &self.canvas,
+            DrawParam::new()
+                .dest([offset_x, offset_y])
+                .scale([scale, scale]),
+        )?;
+
+        if scanlines {
+            draw_scanlines(ctx, offset_x, offset_y, scale)?;
+        }
+
+        let game_scale = scale / PIXEL_SCALE;
+        let visible = Rect::new(
+            -offset_x / game_scale,
+            -offset_y / game_scale,
+            width / game_scale,
+            height / game_scale,
+        );
+        graphics::set_screen_coordinates(ctx, visible)?;
+        Ok(visible)
+    }
+}
+
+// a cheap crt look: the bottom part of every native pixel row is darkened, and the edges of the
+// screen get a bit of a vignette
+fn draw_scanlines(ctx: &mut Context, offset_x: f32, offset_y: f32, scale: f32) -> GameResult {
+    let (width, height) = (
+        f32::from(NATIVE_WIDTH) * scale,
+        f32::from(NATIVE_HEIGHT) * scale,
+    );
+    let line_height = (scale / 3.0).max(1.0).round();
+
+    let mut builder = MeshBuilder::new();
+    (0..NATIVE_HEIGHT).for_each(|row| {
+        let y = offset_y + (f32::from(row) + 1.0) * scale - line_height;
+        builder.rectangle(
+            DrawMode::fill(),
+            Rect::new(offset_x, y, width, line_height),
+            Color::new(0.0, 0.0, 0.0, 0.35),
+        );
+    });
+
+    // each ring covers a band two native pixels wide, darker towards the edge
+    (0..4).for_each(|i| {
+        let inset = (i as f32 * 2.0 + 1.0) * scale;
+        builder.rectangle(
+            DrawMode::stroke(scale * 2.0),
+            Rect::new(
+                offset_x + inset,
+                offset_y + inset,
+                width - inset * 2.0,
+                height - inset * 2.0,
+            ),
+            Color::new(0.0, 0.0, 0.0, 0.2 - i as f32 * 0.05),
+        );
+    });
+
+    let mesh = builder.build(ctx)?;
+    graphics::draw(ctx, &mesh, DrawParam::new())
+}
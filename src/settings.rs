@@ -0,0 +1,193 @@
+use ggez::conf::{FullscreenType, WindowMode, WindowSetup};
+use ggez::input::keyboard::{self, KeyCode};
+use ggez::Context;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ecs::resources::{Difficulty, Ship};
+
+// saved in the user's config directory
+const SETTINGS_FILE: &str = "settings.ron";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WindowType {
+    Windowed,
+    Fullscreen,
+    // fullscreen at the desktop resolution, without changing the display mode
+    Borderless,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Shoot,
+    Focus,
+    Deflect,
+    Pause,
+}
+
+pub const ACTIONS: [Action; 8] = [
+    Action::Up,
+    Action::Down,
+    Action::Left,
+    Action::Right,
+    Action::Shoot,
+    Action::Focus,
+    Action::Deflect,
+    Action::Pause,
+];
+
+// every action can have several keys
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub up: Vec<KeyCode>,
+    pub down: Vec<KeyCode>,
+    pub left: Vec<KeyCode>,
+    pub right: Vec<KeyCode>,
+    pub shoot: Vec<KeyCode>,
+    pub focus: Vec<KeyCode>,
+    pub deflect: Vec<KeyCode>,
+    pub pause: Vec<KeyCode>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            up: vec![KeyCode::W, KeyCode::Up],
+            down: vec![KeyCode::S, KeyCode::Down],
+            left: vec![KeyCode::A, KeyCode::Left],
+            right: vec![KeyCode::D, KeyCode::Right],
+            shoot: vec![KeyCode::Space],
+            focus: vec![KeyCode::LShift],
+            deflect: vec![KeyCode::LControl],
+            pause: vec![KeyCode::Escape],
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn keys(&self, action: Action) -> &Vec<KeyCode> {
+        match action {
+            Action::Up => &self.up,
+            Action::Down => &self.down,
+            Action::Left => &self.left,
+            Action::Right => &self.right,
+            Action::Shoot => &self.shoot,
+            Action::Focus => &self.focus,
+            Action::Deflect => &self.deflect,
+            Action::Pause => &self.pause,
+        }
+    }
+
+    pub fn keys_mut(&mut self, action: Action) -> &mut Vec<KeyCode> {
+        match action {
+            Action::Up => &mut self.up,
+            Action::Down => &mut self.down,
+            Action::Left => &mut self.left,
+            Action::Right => &mut self.right,
+            Action::Shoot => &mut self.shoot,
+            Action::Focus => &mut self.focus,
+            Action::Deflect => &mut self.deflect,
+            Action::Pause => &mut self.pause,
+        }
+    }
+
+    pub fn is_pressed(&self, ctx: &Context, action: Action) -> bool {
+        self.keys(action)
+            .iter()
+            .any(|key| keyboard::is_key_pressed(ctx, *key))
+    }
+
+    pub fn is_bound(&self, keycode: KeyCode, action: Action) -> bool {
+        self.keys(action).contains(&keycode)
+    }
+
+    // binding a key that's already used elsewhere takes it away from the other action
+    pub fn rebind(&mut self, action: Action, keycode: KeyCode) {
+        ACTIONS.iter().for_each(|other| {
+            self.keys_mut(*other).retain(|key| *key != keycode);
+        });
+        self.keys_mut(action).insert(0, keycode);
+    }
+}
+
+// missing fields fall back to their defaults so old settings files keep working
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub window_size: (f32, f32),
+    pub window_type: WindowType,
+    // only applied on startup
+    pub vsync: bool,
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub screen_shake: bool,
+    // crt style scanlines over the game
+    pub scanlines: bool,
+    pub key_bindings: KeyBindings,
+    // these take effect when the next run starts
+    pub difficulty: Difficulty,
+    // dynamic difficulty, see Rank
+    pub rank: bool,
+    // the name of the ship picked last
+    pub ship: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            window_size: (crate::SCREEN_WIDTH, crate::SCREEN_HEIGHT),
+            window_type: WindowType::Windowed,
+            vsync: true,
+            master_volume: 1.0,
+            music_volume: 1.0,
+            sfx_volume: 1.0,
+            screen_shake: true,
+            scanlines: false,
+            key_bindings: KeyBindings::default(),
+            difficulty: Difficulty::default(),
+            rank: false,
+            ship: Ship::default().name,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        crate::save::load(SETTINGS_FILE)
+    }
+
+    pub fn save(&self) {
+        crate::save::save(SETTINGS_FILE, self)
+    }
+
+    pub fn window_mode(&self) -> WindowMode {
+        let (width, height) = self.window_size;
+        let fullscreen_type = match self.window_type {
+            WindowType::Windowed => FullscreenType::Windowed,
+            WindowType::Fullscreen => FullscreenType::True,
+            WindowType::Borderless => FullscreenType::Desktop,
+        };
+
+        WindowMode::default()
+            .dimensions(width, height)
+            .fullscreen_type(fullscreen_type)
+            .resizable(true)
+    }
+
+    pub fn window_setup(&self) -> WindowSetup {
+        WindowSetup::default().title("ssshmup").vsync(self.vsync)
+    }
+
+    // applies the settings which can be changed while the game is running
+    pub fn apply_window_mode(&self, ctx: &mut Context) {
+        if let Err(e) = ggez::graphics::set_mode(ctx, self.window_mode()) {
+            log::warn!("error changing window mode: {}", e);
+        }
+    }
+}
@@ -0,0 +1,504 @@
+use specs::prelude::*;
+
+use crate::ecs::components::*;
+use crate::ecs::resources::*;
+use crate::ecs::systems::{self, WithTimed};
+
+use rand::Rng;
+
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::profiler::Profiler;
+
+// the gameplay part of the game loop, without ggez, so that it can also run headless for
+// benchmarks. GameState handles input, drawing and sound around it
+
+// what the player is doing this frame, which keys are held
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Input {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+    pub shoot: bool,
+    // much slower movement and a concentrated shot, for dodging through tight gaps
+    pub focus: bool,
+    // charges the deflector while held, it turns on when let go
+    pub deflect: bool,
+}
+
+// a world with every component registered and the default global values, but no assets
+// and no player
+pub fn create_world() -> World {
+    // initialize specs world and register components
+    // not all components have to be registered here if they're used in systems
+    // but it doesn't hurt
+    let mut world = World::new();
+    world.register::<Position>();
+    world.register::<Player>();
+    world.register::<Velocity>();
+    world.register::<ColorRect>();
+    world.register::<HP>();
+    world.register::<Enemy>();
+    world.register::<Bullet>();
+    world.register::<Sprite>();
+    world.register::<AnimatedSprite>();
+    world.register::<Hitbox>();
+    world.register::<Particle>();
+    world.register::<ParticleEmitter>();
+    world.register::<Star>();
+    world.register::<Deflected>();
+    world.register::<Grazing>();
+    world.register::<Grazed>();
+    world.register::<ShipSprites>();
+
+    // controls star generation, each stage has its own parallax layers
+    {
+        use ggez::graphics::Color;
+
+        let layer = |num_stars, size, vel, color, twinkle| StarLayer {
+            num_stars,
+            size,
+            size_variance: size * 0.4,
+            vel,
+            vel_variance: vel * 0.3,
+            color,
+            twinkle,
+            image: None,
+        };
+
+        world.insert(StarInfo::new(vec![
+            StarStage {
+                start_wave: 0,
+                layers: vec![
+                    layer(35, 1.5, 2.5, Color::new(1.0, 1.0, 1.0, 0.25), 0.6),
+                    layer(25, 2.5, 5.0, Color::new(1.0, 1.0, 1.0, 0.35), 0.2),
+                    layer(8, 3.5, 8.0, Color::new(1.0, 1.0, 1.0, 0.45), 0.0),
+                ],
+            },
+            StarStage {
+                start_wave: 6,
+                layers: vec![
+                    layer(45, 1.5, 3.0, Color::new(0.6, 0.7, 1.0, 0.3), 0.7),
+                    layer(25, 2.5, 6.0, Color::new(0.7, 0.8, 1.0, 0.35), 0.3),
+                    layer(10, 4.0, 10.0, Color::new(0.9, 0.9, 1.0, 0.45), 0.0),
+                ],
+            },
+            StarStage {
+                start_wave: 12,
+                layers: vec![
+                    layer(45, 1.5, 3.5, Color::new(1.0, 0.6, 0.5, 0.3), 0.7),
+                    layer(30, 2.5, 7.0, Color::new(1.0, 0.75, 0.6, 0.35), 0.3),
+                    layer(12, 4.0, 12.0, Color::new(1.0, 0.9, 0.8, 0.45), 0.1),
+                ],
+            },
+        ]));
+    }
+
+    // particles past this cap are dropped
+    world.insert(ParticleInfo { max_particles: 600 });
+    world.insert(QueuedParticles(Vec::new()));
+
+    // default global values
+    world.insert(LastUpdate(std::time::Duration::new(0, 0)));
+    world.insert(CurrentWave(0));
+    world.insert(QueuedEnemies(Vec::new()));
+    world.insert(FramesToNextWave(30));
+    world.insert(Dead(false));
+    world.insert(GodMode(false));
+    world.insert(Difficulty::default());
+    world.insert(Rank::default());
+    world.insert(Ship::default());
+    world.insert(Ships::default());
+    world.insert(Balance::default());
+    world.insert(Score::default());
+    world.insert(GrazeCount::default());
+    world.insert(Camera::default());
+    world.insert(DesiredMusic(None));
+    world.insert(QueuedSounds(Vec::new()));
+    world.insert(GameRng::default());
+    world.insert(FrameEvents::default());
+
+    world
+}
+
+// a world with the assets loaded from resources_dir without a ggez context, and gameplay
+// randomness from `seed`. there's no player yet
+pub fn headless_world(resources_dir: &Path, seed: u64) -> Result<World, String> {
+    let mut world = create_world();
+    let errors = crate::assets::load_headless(resources_dir, &mut world);
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    world.insert(GameRng::seeded(seed));
+    Ok(world)
+}
+
+pub fn create_dispatcher<'a, 'b>() -> Dispatcher<'a, 'b> {
+    DispatcherBuilder::new()
+        .with_timed(systems::EnemyMoveSys, "enemy_move_sys", &[])
+        .with_timed(systems::BulletTrackingSys, "tracking_bullet_sys", &[])
+        .with_timed(systems::BounceBulletSys, "bouncing_bullet_sys", &[])
+        .with_timed(systems::IntegrateSys, "integrate_system", &[])
+        .with_timed(systems::StarStageSys, "star_stage_sys", &[])
+        .with_timed(systems::StarMoveSys, "star_system", &["star_stage_sys"])
+        .with_timed(systems::ReloadTimerSys, "reload_timer_sys", &[])
+        .with_timed(systems::DeflectorSys, "deflector_timer_sys", &[])
+        .with_timed(systems::EnemyShootSys, "enemy_shoot_sys", &[])
+        .with_timed(systems::AnimationSys, "animation_sys", &[])
+        .with_timed(
+            systems::BulletCollSys,
+            "bullet_coll_sys",
+            &["integrate_system", "bouncing_bullet_sys"],
+        )
+        .with_timed(
+            systems::PlayerCollSys,
+            "player_coll_sys",
+            &["integrate_system"],
+        )
+        .with_timed(
+            systems::HPKillSys,
+            "hp_kill_sys",
+            &["bullet_coll_sys", "player_coll_sys"],
+        )
+        .with_timed(systems::IFrameSys, "iframe_sys", &["hp_kill_sys"])
+        .with_timed(
+            systems::ParticleEmitterSys,
+            "particle_emitter_sys",
+            &["integrate_system"],
+        )
+        .with_timed(
+            systems::ParticleSys,
+            "particle_sys",
+            &["hp_kill_sys", "particle_emitter_sys"],
+        )
+        .with_timed(
+            systems::MusicDirectorSys,
+            "music_director_sys",
+            &["hp_kill_sys"],
+        )
+        .with_timed(
+            systems::RankSys,
+            "rank_sys",
+            &["bullet_coll_sys", "player_coll_sys"],
+        )
+        .with_timed(
+            systems::CameraSys,
+            "camera_sys",
+            &["bullet_coll_sys", "player_coll_sys", "hp_kill_sys"],
+        )
+        .build()
+}
+
+// the balance of the difficulty in the world
+pub fn difficulty_stats(world: &World) -> DifficultyStats {
+    let difficulty = *world.fetch::<Difficulty>();
+    world.fetch::<Balance>().difficulty(difficulty)
+}
+
+// needs the spritesheets to be loaded. the player flies the ship in the world, and the deflector
+// cools down as fast as the difficulty allows
+pub fn spawn_player(world: &mut World, hp: u32) {
+    let ship = (*world.fetch::<Ship>()).clone();
+    let hp = (hp as i32 + ship.extra_hp).max(1) as u32;
+    let mut player = new_player(hp, &ship);
+    let reload_frames = &mut (player.4).deflector.reload_frames;
+    *reload_frames =
+        (*reload_frames as f32 * difficulty_stats(world).deflector_reload).round() as u32;
+    let player = create_player(world, player);
+    world.insert(PlayerEntity(player));
+}
+
+// starts a new run after the player died, at the difficulty in the world
+pub fn restart(world: &mut World) {
+    let respawn_hp = difficulty_stats(world).respawn_hp;
+    start_run(world, respawn_hp);
+}
+
+// clears everything from the last run, including the player if it's still alive, and spawns a
+// new player with `hp`
+pub fn start_run(world: &mut World, hp: u32) {
+    {
+        let entities = world.entities();
+        let enemies = world.read_storage::<Enemy>();
+        let bullets = world.read_storage::<Bullet>();
+        let players = world.read_storage::<Player>();
+        entities.join().for_each(|entity| {
+            if enemies.get(entity).is_some()
+                || bullets.get(entity).is_some()
+                || players.get(entity).is_some()
+            {
+                entities
+                    .delete(entity)
+                    .expect("error deleting enemy, bullet or player");
+            }
+        });
+    }
+    world.maintain();
+
+    spawn_player(world, hp);
+    world.insert(Dead(false));
+    world.insert(CurrentWave(0));
+    world.fetch_mut::<Score>().current = 0;
+    world.insert(GrazeCount::default());
+    let rank_enabled = world.fetch::<Rank>().enabled;
+    world.insert(Rank::new(rank_enabled));
+}
+
+// the deflector charges while deflect is held once it has cooled down, and turns on when it's
+// let go
+pub fn charge_deflector(world: &mut World, held: bool) {
+    if world.fetch::<Dead>().0 {
+        return;
+    }
+
+    let mut players = world.write_storage::<Player>();
+    let player_entity = world.fetch::<PlayerEntity>().0;
+    let player = players
+        .get_mut(player_entity)
+        .expect("error getting player entity");
+
+    if held {
+        if player.deflector_cooldown == 0 {
+            player.deflector_held_frames += 1;
+        }
+    } else if player.deflector_held_frames > 0 {
+        let level = player.charge_level();
+        player.deflector_level = level;
+        player.deflector_timer = player.deflector.active_frames;
+        player.deflector_cooldown = player.deflector.reload_frames
+            + player.deflector.level_cooldown * level.saturating_sub(1);
+        player.deflector_held_frames = 0;
+    }
+}
+
+// runs one frame of the game
+pub fn step(world: &mut World, dispatcher: &mut Dispatcher, input: Input) {
+    world.insert(FrameEvents::default());
+    let dead = world.fetch::<Dead>().0;
+
+    charge_deflector(world, input.deflect);
+
+    if !dead {
+        let player_entity = world.fetch::<PlayerEntity>().0;
+        if let Some(player) = world.write_storage::<Player>().get_mut(player_entity) {
+            player.focused = input.focus;
+        }
+    }
+
+    // player shooting
+    if !dead && input.shoot {
+        let mut spawn_sys = systems::SpawnBulletSys;
+        spawn_sys.run_now(world);
+    }
+
+    spawn_waves(world);
+
+    if !dead {
+        // player movement controls
+        let player_entity = world.fetch::<PlayerEntity>().0;
+        let velocities = &mut world.write_storage::<Velocity>();
+        let positions = &mut world.write_storage::<Position>();
+        let player_vel = &mut velocities
+            .get_mut(player_entity)
+            .expect("error getting player_vel")
+            .0;
+        let player_pos = &mut positions
+            .get_mut(player_entity)
+            .expect("error getting player pos")
+            .0;
+
+        let ship_speed = world
+            .read_storage::<Player>()
+            .get(player_entity)
+            .map(|player| player.speed)
+            .unwrap_or(1.0);
+        steer_player(player_pos, player_vel, input, ship_speed);
+    }
+
+    dispatcher.dispatch_par(world);
+    world.maintain();
+}
+
+// the player's acceleration from the held keys, and keeping it on screen. `ship_speed` is the
+// ship's Player::speed. IntegrateSys moves it afterwards
+pub fn steer_player(pos: &mut Point, vel: &mut Vector, input: Input, ship_speed: f32) {
+    *vel /= 1.45;
+
+    let speed = ship_speed
+        * if input.focus {
+            0.6
+        } else if input.shoot {
+            1.3
+        } else {
+            1.7
+        };
+
+    if input.up && pos.y > 0.0 {
+        vel.y -= speed;
+    }
+    if input.down && pos.y < crate::SCREEN_HEIGHT - 45.0 {
+        vel.y += speed;
+    }
+    if input.left && pos.x > 0.0 {
+        vel.x -= speed;
+    }
+    if input.right && pos.x < crate::SCREEN_WIDTH - 45.0 {
+        vel.x += speed;
+    }
+
+    pos.y = pos.y.clamp(0.0, crate::SCREEN_HEIGHT - 45.0);
+    pos.x = pos.x.clamp(0.0, crate::SCREEN_WIDTH - 45.0);
+}
+
+// runs `frames` frames of scripted play without a window, the enemies and their bullets are
+// the same every run with the same seed. the player can't die so that every frame is played
+pub fn bench(resources_dir: &Path, frames: u32, seed: u64) -> Result<Profiler, String> {
+    let mut world = headless_world(resources_dir, seed)?;
+    world.insert(GodMode(true));
+    world.insert(SystemTimings(Mutex::new(Profiler::new(frames as usize))));
+    let starting_hp = difficulty_stats(&world).starting_hp;
+    spawn_player(&mut world, starting_hp);
+
+    let mut dispatcher = create_dispatcher();
+    dispatcher.setup(&mut world);
+
+    (0..frames).for_each(|frame| {
+        let start = Instant::now();
+        step(&mut world, &mut dispatcher, bench_input(frame));
+        let timings = world.fetch::<SystemTimings>();
+        timings.record("update", start, start.elapsed());
+        timings.end_frame();
+    });
+
+    let profiler = world
+        .fetch::<SystemTimings>()
+        .0
+        .lock()
+        .expect("error locking system timings")
+        .clone();
+    Ok(profiler)
+}
+
+// sweeps from side to side while shooting, and deflects every few seconds
+fn bench_input(frame: u32) -> Input {
+    let going_left = (frame / 120) % 2 == 1;
+    Input {
+        left: going_left,
+        right: !going_left,
+        shoot: true,
+        deflect: frame % 150 == 75,
+        ..Input::default()
+    }
+}
+
+// the queued enemies of a wave, each one as a mirrored pair that moves the same way, either
+// side to side or up and down. they reload as fast as the difficulty and rank say
+pub fn wave_enemies(
+    queued_enemies: &[(Point, EnemyType)],
+    balance: &Balance,
+    difficulty: Difficulty,
+    rank: &Rank,
+    rng: &mut impl Rng,
+) -> Vec<EnemyTuple> {
+    let reload_scale = balance.difficulty(difficulty).enemy_reload / rank.fire_rate();
+    queued_enemies
+        .iter()
+        .flat_map(|(pos, et)| {
+            let (mt1, mt2) = {
+                let mt = rng.gen_range(0, 2);
+                let mt2_x = crate::SCREEN_WIDTH - 90.0 - pos.x;
+                match mt {
+                    0 => (
+                        MovementType::horizontal(pos.x, 75.0, 1.0),
+                        MovementType::horizontal(mt2_x, 75.0, 1.0),
+                    ),
+                    1 => (
+                        MovementType::vertical(pos.y, 90.0, 1.0),
+                        MovementType::vertical(pos.y, 90.0, 1.0),
+                    ),
+                    // 2 => (MovementType::circle(*pos, 60.0, 1.0), MovementType::circle(Point::new(mt2_x, pos.y), 60.0, 1.0)),
+                    _ => unreachable!(),
+                }
+            };
+            let mut enemy = new_enemy(*et, *pos, mt1, balance, rng);
+            let reload = &mut enemy.2;
+            reload.reload_speed = (reload.reload_speed as f32 * reload_scale).round() as u32;
+            reload.reload_timer = (reload.reload_timer as f32 * reload_scale).round() as u32;
+
+            let mut enemy_2 = enemy.clone();
+            (enemy_2.0).0 = Point::new(crate::SCREEN_WIDTH - 90.0 - pos.x, pos.y);
+            (enemy_2.2).movement = mt2;
+
+            vec![enemy, enemy_2]
+        })
+        .collect()
+}
+
+// spawn new enemies if all enemies are dead
+fn spawn_waves(world: &mut World) {
+    let num_enemies = {
+        let enemies = world.read_storage::<Enemy>();
+        enemies.join().count()
+    };
+    if num_enemies == 0 {
+        {
+            let frames_to_next_wave = &mut world.fetch_mut::<FramesToNextWave>().0;
+            if *frames_to_next_wave != 0 {
+                *frames_to_next_wave -= 1;
+                return;
+            }
+        }
+
+        {
+            let current_wave = &mut world.fetch_mut::<CurrentWave>().0;
+            *current_wave = current_wave.saturating_add(1);
+        }
+
+        // rank changes before the next wave is picked, so that it already counts
+        {
+            let player_entity = world.fetch::<PlayerEntity>().0;
+            let starting_hp = difficulty_stats(world).starting_hp;
+            if let Some(hp) = world.read_storage::<HP>().get(player_entity) {
+                world
+                    .fetch_mut::<Rank>()
+                    .end_wave(hp.remaining, starting_hp);
+            }
+        }
+
+        {
+            let mut wave_calc_sys = systems::WaveCalcSys;
+            wave_calc_sys.run_now(world);
+        }
+
+        let wave_enemies = {
+            let queued_enemies = &world.fetch::<QueuedEnemies>().0;
+            let difficulty = *world.fetch::<Difficulty>();
+            wave_enemies(
+                queued_enemies,
+                &world.fetch::<Balance>(),
+                difficulty,
+                &world.fetch::<Rank>(),
+                &mut world.fetch_mut::<GameRng>().0,
+            )
+        };
+        let enemy_hp = wave_enemies.iter().map(|enemy| enemy.3.remaining).sum();
+        world.fetch_mut::<Rank>().start_wave(enemy_hp);
+
+        if world.fetch::<CurrentWave>().0 != 1 {
+            let wave_heal = difficulty_stats(world).wave_heal;
+            let player_entity = world.fetch::<PlayerEntity>().0;
+            if let Some(player_hp) = world.write_storage::<HP>().get_mut(player_entity) {
+                player_hp.remaining += wave_heal;
+            }
+        }
+
+        wave_enemies.into_iter().for_each(|enemy| {
+            create_enemy(world, enemy);
+        });
+    } else if world.fetch::<FramesToNextWave>().0 == 0 {
+        world.insert(FramesToNextWave::default());
+    }
+}
@@ -0,0 +1,130 @@
+use specs::prelude::*;
+
+use std::fmt::Write as _;
+
+use crate::ecs::components::*;
+use crate::ecs::resources::*;
+use crate::ecs::systems::WaveCalcSys;
+use crate::sim;
+
+// the enemies WaveCalcSys picks for a wave and what they add up to, without playing it
+#[derive(Clone, Debug, PartialEq)]
+pub struct WaveStats {
+    pub wave: u8,
+    pub seed: u64,
+    pub difficulty: Difficulty,
+    // how many of each of ENEMY_TYPES, counting both enemies of a pair
+    pub counts: [u32; ENEMY_TYPES.len()],
+    pub bullets_per_second: f32,
+    pub total_hp: u32,
+    pub total_difficulty: u16,
+    pub budget: u16,
+    // until the first enemy shoots, enemies start partway through their reload
+    pub first_shot_seconds: f32,
+}
+
+// waves 1..=`waves` for one seed. the random parts are drawn from the seed in order like in a
+// run, but a run also uses the rng for bullets, so a played run with the same seed can differ
+pub fn wave_stats(
+    waves: u8,
+    seed: u64,
+    difficulty: Difficulty,
+    balance: &Balance,
+) -> Vec<WaveStats> {
+    let mut world = sim::create_world();
+    world.insert(GameRng::seeded(seed));
+    world.insert(difficulty);
+    world.insert(balance.clone());
+    let mut wave_calc_sys = WaveCalcSys;
+
+    (1..=waves)
+        .map(|wave| {
+            world.insert(CurrentWave(wave));
+            wave_calc_sys.run_now(&world);
+            let enemies = {
+                let queued_enemies = &world.fetch::<QueuedEnemies>().0;
+                sim::wave_enemies(
+                    queued_enemies,
+                    balance,
+                    difficulty,
+                    &Rank::default(),
+                    &mut world.fetch_mut::<GameRng>().0,
+                )
+            };
+
+            let mut counts = [0; ENEMY_TYPES.len()];
+            enemies.iter().for_each(|(_, _, enemy, _, _)| {
+                if let Some(i) = ENEMY_TYPES.iter().position(|ty| *ty == enemy.ty) {
+                    counts[i] += 1;
+                }
+            });
+
+            // an enemy shoots when its reload timer has counted down to 0, so once every
+            // reload_speed + 1 frames
+            let bullets_per_second = enemies
+                .iter()
+                .map(|(_, _, enemy, _, _)| 60.0 / (enemy.reload_speed + 1) as f32)
+                .sum();
+            let first_shot_frames = enemies
+                .iter()
+                .map(|(_, _, enemy, _, _)| enemy.reload_timer + 1)
+                .min()
+                .unwrap_or(0);
+
+            WaveStats {
+                wave,
+                seed,
+                difficulty,
+                counts,
+                bullets_per_second,
+                total_hp: enemies.iter().map(|(_, _, _, hp, _)| hp.remaining).sum(),
+                // both enemies of a pair count, which is why WaveCalcSys counts each pick twice
+                total_difficulty: enemies
+                    .iter()
+                    .map(|(_, _, enemy, _, _)| balance.enemy(enemy.ty).cost)
+                    .sum(),
+                budget: balance.wave_budget(wave, difficulty),
+                first_shot_seconds: first_shot_frames as f32 / 60.0,
+            }
+        })
+        .collect()
+}
+
+// e.g. "aim2" for AimEnemy2, the short names the debug console takes
+fn column_name(ty: EnemyType) -> String {
+    format!("{:?}", ty).replacen("Enemy", "", 1).to_lowercase()
+}
+
+pub fn csv(stats: &[WaveStats]) -> String {
+    let mut text = String::from("wave,seed,difficulty,enemies,");
+    ENEMY_TYPES.iter().for_each(|ty| {
+        write!(text, "{},", column_name(*ty)).expect("error formatting csv");
+    });
+    text.push_str("bullets_per_second,total_hp,total_difficulty,budget,first_shot_seconds\n");
+
+    stats.iter().for_each(|stats| {
+        write!(
+            text,
+            "{},{},{:?},{},",
+            stats.wave,
+            stats.seed,
+            stats.difficulty,
+            stats.counts.iter().sum::<u32>()
+        )
+        .expect("error formatting csv");
+        stats.counts.iter().for_each(|count| {
+            write!(text, "{},", count).expect("error formatting csv");
+        });
+        writeln!(
+            text,
+            "{:.2},{},{},{},{:.2}",
+            stats.bullets_per_second,
+            stats.total_hp,
+            stats.total_difficulty,
+            stats.budget,
+            stats.first_shot_seconds
+        )
+        .expect("error formatting csv");
+    });
+    text
+}